@@ -0,0 +1,325 @@
+use crate::{
+    organism::{Action, Direction, Organism},
+    sensors::SenseContext,
+    utils::bernoulli_trial,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+//outputs of the brain, the enabled action neurons (in config order) make up the output layer.
+//Outputs are clamped to -1..1 (the action layer can use an unbounded activation), most neurons
+//fire with a probability of their (positive) output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionNeuron {
+    MoveX,
+    MoveY,
+    MoveForward,
+    TurnLeft,
+    TurnRight,
+    MoveRandom,
+    MoveToward,
+    MoveAway,
+    EmitPheromone,
+    Kill,
+    SetOscillator,
+}
+
+impl ActionNeuron {
+    pub const ALL: [ActionNeuron; 11] = [
+        ActionNeuron::MoveX,
+        ActionNeuron::MoveY,
+        ActionNeuron::MoveForward,
+        ActionNeuron::TurnLeft,
+        ActionNeuron::TurnRight,
+        ActionNeuron::MoveRandom,
+        ActionNeuron::MoveToward,
+        ActionNeuron::MoveAway,
+        ActionNeuron::EmitPheromone,
+        ActionNeuron::Kill,
+        ActionNeuron::SetOscillator,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ActionNeuron::MoveX => "Mx",
+            ActionNeuron::MoveY => "My",
+            ActionNeuron::MoveForward => "Mf",
+            ActionNeuron::TurnLeft => "Tl",
+            ActionNeuron::TurnRight => "Tr",
+            ActionNeuron::MoveRandom => "Mr",
+            ActionNeuron::MoveToward => "Mt",
+            ActionNeuron::MoveAway => "Ma",
+            ActionNeuron::EmitPheromone => "Ep",
+            ActionNeuron::Kill => "Kl",
+            ActionNeuron::SetOscillator => "So",
+        }
+    }
+
+    //name used in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionNeuron::MoveX => "move_x",
+            ActionNeuron::MoveY => "move_y",
+            ActionNeuron::MoveForward => "move_forward",
+            ActionNeuron::TurnLeft => "turn_left",
+            ActionNeuron::TurnRight => "turn_right",
+            ActionNeuron::MoveRandom => "move_random",
+            ActionNeuron::MoveToward => "move_toward",
+            ActionNeuron::MoveAway => "move_away",
+            ActionNeuron::EmitPheromone => "emit_pheromone",
+            ActionNeuron::Kill => "kill",
+            ActionNeuron::SetOscillator => "set_oscillator",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ActionNeuron::MoveX => "Move Left Right (-/+)",
+            ActionNeuron::MoveY => "Move Down Up (-/+)",
+            ActionNeuron::MoveForward => "Move in the heading direction",
+            ActionNeuron::TurnLeft => "Turn the heading 90 degrees left without moving",
+            ActionNeuron::TurnRight => "Turn the heading 90 degrees right without moving",
+            ActionNeuron::MoveRandom => "Move in a random direction",
+            ActionNeuron::MoveToward => "Move toward the nearest neighbour",
+            ActionNeuron::MoveAway => "Move away from the nearest neighbour",
+            ActionNeuron::EmitPheromone => "Leave pheromone on the current cell",
+            ActionNeuron::Kill => "Kill the neighbour ahead",
+            ActionNeuron::SetOscillator => {
+                "Set the oscillator period (half to double oscillator_period)"
+            }
+        }
+    }
+
+    //the action this neuron triggers for the given output, if any
+    pub fn act(
+        &self,
+        output: f32,
+        organism: &Organism,
+        context: &SenseContext,
+        rng: &mut impl Rng,
+    ) -> Option<Action> {
+        let output = match output.is_nan() {
+            true => 0.0,
+            false => output.clamp(-1.0, 1.0),
+        };
+
+        //signed neurons pick a direction by the sign of their output
+        if let ActionNeuron::MoveX | ActionNeuron::MoveY = self {
+            let (positive, negative) = match self {
+                ActionNeuron::MoveX => (Direction::East, Direction::West),
+                _ => (Direction::North, Direction::South),
+            };
+            return if output > 0.0 && bernoulli_trial(output, rng) {
+                Some(Action::Move(positive))
+            } else if output < 0.0 && bernoulli_trial(-output, rng) {
+                Some(Action::Move(negative))
+            } else {
+                None
+            };
+        }
+
+        if let ActionNeuron::SetOscillator = self {
+            let period = context.generation.brain.oscillator_period as f32 * 2f32.powf(output);
+            return Some(Action::SetOscillatorPeriod(period));
+        }
+
+        if !(output > 0.0 && bernoulli_trial(output, rng)) {
+            return None;
+        }
+
+        match self {
+            ActionNeuron::MoveForward => Some(Action::Move(organism.heading)),
+            ActionNeuron::TurnLeft => Some(Action::Turn(-2)),
+            ActionNeuron::TurnRight => Some(Action::Turn(2)),
+            ActionNeuron::MoveRandom => Some(Action::Move(Direction::random(rng))),
+            ActionNeuron::MoveToward | ActionNeuron::MoveAway => {
+                let position = (organism.position.x as usize, organism.position.y as usize);
+                let radius = context.generation.brain.neighbourhood_radius;
+                let (x, y) = context
+                    .environment
+                    .neighbours(position, radius)
+                    .into_iter()
+                    .map(|(cell, _)| cell)
+                    .min_by_key(|cell| {
+                        let (dx, dy) = context.environment.offset(position, *cell);
+                        dx * dx + dy * dy
+                    })?;
+                let (dx, dy) = context.environment.offset(position, (x, y));
+                let toward = (dx.signum(), dy.signum());
+                let offset = match self {
+                    ActionNeuron::MoveToward => toward,
+                    _ => (-toward.0, -toward.1),
+                };
+                Direction::from_offset(offset).map(Action::Move)
+            }
+            ActionNeuron::EmitPheromone => Some(Action::EmitPheromone),
+            ActionNeuron::Kill => Some(Action::Kill(organism.heading)),
+            ActionNeuron::MoveX | ActionNeuron::MoveY | ActionNeuron::SetOscillator => None,
+        }
+    }
+}
+
+//markdown table of every action neuron, kept in the README
+pub fn action_table() -> String {
+    let mut table = String::from("| Code | Config name | Description |\n|---|---|---|\n");
+    for neuron in ActionNeuron::ALL {
+        table += &format!(
+            "| {} | `{}` | {} |\n",
+            neuron.code(),
+            neuron.name(),
+            neuron.description()
+        );
+    }
+    table
+}
+
+mod tests {
+    use super::*;
+    use crate::{config::Config, environment::Environment, generation::Generation};
+    use crate::{
+        gene::Genome,
+        neural::{Activation, BrainState, NeuralNet},
+    };
+    use bevy::{color::palettes::css::BLACK, math::Vec2, prelude::Color};
+    use std::collections::HashMap;
+
+    #[test]
+    fn readme_lists_every_action() {
+        assert!(
+            include_str!("../README.md").contains(&action_table()),
+            "README gene table is out of date, regenerate it with --gene-table"
+        );
+    }
+
+    fn organism_with(brain: NeuralNet) -> Organism {
+        Organism {
+            genome: Genome::from(brain.clone()),
+            brain,
+            position: Vec2::new(4.0, 4.0),
+            heading: Direction::East,
+            last_move: None,
+            oscillator_period: None,
+            energy: 0.0,
+            species: None,
+            memory: BrainState::default(),
+        }
+    }
+
+    #[test]
+    fn act_on_outputs() {
+        // 10 x 10 grid with a neighbour north east of the organism
+        let mut environment =
+            Environment::new(50.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        environment.organisms.set(4, 4, 1);
+        environment.organisms.set(5, 5, 2);
+        let config = Config::default();
+        let generation = Generation::new(
+            100,
+            10,
+            config.selection.criterion(),
+            config.mutation,
+            config.generation.reproduction,
+            config.brain,
+            config.energy,
+        );
+        let organisms = HashMap::new();
+        let context = SenseContext {
+            environment: &environment,
+            organisms: &organisms,
+            generation: &generation,
+        };
+        let mut organism = organism_with(NeuralNet::new(vec![2, 2]));
+        let rng = &mut rand::thread_rng();
+        let mut act = |neuron: ActionNeuron, output: f32, organism: &Organism| {
+            neuron.act(output, organism, &context, rng)
+        };
+
+        assert!(act(ActionNeuron::MoveX, 1.0, &organism) == Some(Action::Move(Direction::East)));
+        assert!(act(ActionNeuron::MoveY, -1.0, &organism) == Some(Action::Move(Direction::South)));
+        assert!(act(ActionNeuron::MoveX, 0.0, &organism).is_none());
+        assert!(act(ActionNeuron::EmitPheromone, -1.0, &organism).is_none());
+        assert!(
+            act(ActionNeuron::MoveToward, 1.0, &organism)
+                == Some(Action::Move(Direction::NorthEast))
+        );
+        assert!(
+            act(ActionNeuron::MoveAway, 1.0, &organism) == Some(Action::Move(Direction::SouthWest))
+        );
+        assert!(
+            act(ActionNeuron::SetOscillator, 0.0, &organism)
+                == Some(Action::SetOscillatorPeriod(30.0))
+        );
+        assert!(
+            act(ActionNeuron::SetOscillator, -1.0, &organism)
+                == Some(Action::SetOscillatorPeriod(15.0))
+        );
+
+        assert!(
+            act(ActionNeuron::MoveForward, 1.0, &organism) == Some(Action::Move(Direction::East))
+        );
+        assert!(act(ActionNeuron::Kill, 1.0, &organism) == Some(Action::Kill(Direction::East)));
+        organism.heading = Direction::North;
+        assert!(
+            act(ActionNeuron::MoveForward, 1.0, &organism) == Some(Action::Move(Direction::North))
+        );
+        assert!(act(ActionNeuron::TurnLeft, 1.0, &organism) == Some(Action::Turn(-2)));
+        assert!(act(ActionNeuron::TurnRight, 1.0, &organism) == Some(Action::Turn(2)));
+        assert!(Direction::North.rotate(-2) == Direction::West);
+        assert!(Direction::West.rotate(3) == Direction::NorthEast);
+    }
+
+    #[test]
+    fn unbounded_outputs() {
+        let environment = Environment::new(50.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        let config = Config::default();
+        let generation = Generation::new(
+            100,
+            10,
+            config.selection.criterion(),
+            config.mutation,
+            config.generation.reproduction,
+            config.brain,
+            config.energy,
+        );
+        let organisms = HashMap::new();
+        let context = SenseContext {
+            environment: &environment,
+            organisms: &organisms,
+            generation: &generation,
+        };
+
+        //an identity action layer passes the weighted input straight through
+        let mut brain = NeuralNet::new(vec![1, 2]);
+        brain.set_layer_activations(&[Activation::Identity]);
+        brain.add_connection((0, 0), (1, 0), 30.0);
+        brain.add_connection((0, 0), (1, 1), -30.0);
+        let outputs = brain.forward(vec![1.0]);
+        assert!(outputs == vec![30.0, -30.0]);
+
+        let organism = organism_with(brain);
+        let rng = &mut rand::thread_rng();
+        let mut act =
+            |neuron: ActionNeuron, output: f32| neuron.act(output, &organism, &context, rng);
+        assert!(act(ActionNeuron::MoveX, outputs[0]) == Some(Action::Move(Direction::East)));
+        assert!(act(ActionNeuron::MoveY, outputs[1]) == Some(Action::Move(Direction::South)));
+        assert!(act(ActionNeuron::Kill, f32::INFINITY) == Some(Action::Kill(Direction::East)));
+        assert!(act(ActionNeuron::Kill, f32::NAN).is_none());
+
+        //the period stays within half to double the configured period
+        assert!(
+            act(ActionNeuron::SetOscillator, outputs[0]) == Some(Action::SetOscillatorPeriod(60.0))
+        );
+        assert!(
+            act(ActionNeuron::SetOscillator, outputs[1]) == Some(Action::SetOscillatorPeriod(15.0))
+        );
+        for output in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            let Some(Action::SetOscillatorPeriod(period)) =
+                act(ActionNeuron::SetOscillator, output)
+            else {
+                panic!("set_oscillator didn't set the period");
+            };
+            assert!(period.is_finite() && period > 0.0);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+//command line options
+pub const DEFAULT_HEADLESS_GENERATIONS: usize = 100;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    //run without a window, as fast as possible
+    pub headless: bool,
+    //exit after this many generations
+    pub generations: Option<usize>,
+    //seed for every random source, picked at random if not given
+    pub seed: Option<u64>,
+    //snapshot file written on exit (headless) or with F5 (windowed)
+    pub save: Option<PathBuf>,
+    //snapshot file to resume from
+    pub load: Option<PathBuf>,
+    //toml file with the simulation parameters
+    pub config: Option<PathBuf>,
+    //key=value overrides of single config parameters, e.g. world.width=600
+    pub overrides: Vec<String>,
+    //print the markdown table of sensors kept in the README and exit
+    pub gene_table: bool,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => parsed.headless = true,
+                "--gene-table" => parsed.gene_table = true,
+                "--generations" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| String::from("--generations expects a value"))?;
+                    let generations = value
+                        .parse()
+                        .map_err(|_| format!("invalid number of generations: {}", value))?;
+                    parsed.generations = Some(generations);
+                }
+                "--seed" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| String::from("--seed expects a value"))?;
+                    let seed = value
+                        .parse()
+                        .map_err(|_| format!("invalid seed: {}", value))?;
+                    parsed.seed = Some(seed);
+                }
+                "--save" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| String::from("--save expects a path"))?;
+                    parsed.save = Some(PathBuf::from(path));
+                }
+                "--load" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| String::from("--load expects a path"))?;
+                    parsed.load = Some(PathBuf::from(path));
+                }
+                "--config" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| String::from("--config expects a path"))?;
+                    parsed.config = Some(PathBuf::from(path));
+                }
+                "--set" => {
+                    let over = args
+                        .next()
+                        .ok_or_else(|| String::from("--set expects key=value"))?;
+                    parsed.overrides.push(over);
+                }
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        if parsed.headless && parsed.generations.is_none() {
+            parsed.generations = Some(DEFAULT_HEADLESS_GENERATIONS);
+        }
+
+        Ok(parsed)
+    }
+}
+
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert!(parse(&[]) == Ok(Args::default()));
+        assert!(
+            parse(&["--headless"])
+                == Ok(Args {
+                    headless: true,
+                    generations: Some(DEFAULT_HEADLESS_GENERATIONS),
+                    ..Args::default()
+                })
+        );
+        assert!(
+            parse(&["--generations", "5", "--headless", "--seed", "42"])
+                == Ok(Args {
+                    headless: true,
+                    generations: Some(5),
+                    seed: Some(42),
+                    ..Args::default()
+                })
+        );
+        assert!(parse(&["--generations"]).is_err());
+        assert!(parse(&["--generations", "five"]).is_err());
+        assert!(
+            parse(&["--load", "in.evo", "--save", "out.evo"])
+                == Ok(Args {
+                    save: Some(PathBuf::from("out.evo")),
+                    load: Some(PathBuf::from("in.evo")),
+                    ..Args::default()
+                })
+        );
+        assert!(
+            parse(&[
+                "--config",
+                "evo.toml",
+                "--set",
+                "world.width=600",
+                "--set",
+                "brain.connections=8"
+            ]) == Ok(Args {
+                config: Some(PathBuf::from("evo.toml")),
+                overrides: vec![
+                    String::from("world.width=600"),
+                    String::from("brain.connections=8")
+                ],
+                ..Args::default()
+            })
+        );
+        assert!(parse(&["--set"]).is_err());
+        assert!(
+            parse(&["--gene-table"])
+                == Ok(Args {
+                    gene_table: true,
+                    ..Args::default()
+                })
+        );
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert!(parse(&["--save"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+    }
+}
@@ -0,0 +1,792 @@
+use crate::{
+    actions::ActionNeuron,
+    environment::Topology,
+    generation::Reproduction,
+    mutation::{GeneKinds, MutationRates},
+    neural::{Activation, NeuralNet, MAX_PACKED_INDEX, WEIGHT_RANGE},
+    obstacles::{ObstacleConfig, ObstacleMap},
+    selection::{
+        Alive, EastHalf, InCorners, InsideCircle, InsideRectangle, NearWalls, SelectionCriterion,
+    },
+    sensors::Sensor,
+};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path, path::PathBuf};
+
+//simulation parameters, read from a toml file. Every field is optional and falls back to
+//its default, e.g.
+//
+//  [world]
+//  width = 600.0
+//
+//  [brain]
+//  sensors = ["location_x", "location_y", "blocked_forward"]
+//  actions = ["move_forward", "turn_left", "turn_right"]
+//  hidden_layers = [4, 4]
+//
+//  [selection]
+//  kind = "inside_circle"
+//  center = [40.0, 40.0]
+//  radius = 10.0
+//
+//  [obstacles]
+//  map = "maze.txt"
+//  rectangles = [{ min = [10.0, 10.0], max = [20.0, 12.0] }]
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub simulation: SimulationConfig,
+    pub world: WorldConfig,
+    pub population: PopulationConfig,
+    pub brain: BrainConfig,
+    pub mutation: MutationRates,
+    pub generation: GenerationConfig,
+    pub selection: SelectionConfig,
+    pub energy: EnergyConfig,
+    pub obstacles: ObstacleConfig,
+    pub stats: StatsConfig,
+    pub species: SpeciesConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    //seed for every random source, picked at random if not given
+    pub seed: Option<u64>,
+    //seconds between steps in windowed mode
+    pub step_interval: f32,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            seed: None,
+            step_interval: 0.01,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub width: f32,
+    pub height: f32,
+    //side of a grid cell, the grid is width / organism_size cells wide
+    pub organism_size: f32,
+    //share of the pheromone left by the emit_pheromone action that evaporates every step
+    pub pheromone_decay: f32,
+    //bounded, torus, wrap_x or wrap_y
+    pub topology: Topology,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            width: 400.0,
+            height: 400.0,
+            organism_size: 5.0,
+            pheromone_decay: 0.1,
+            topology: Topology::Bounded,
+        }
+    }
+}
+
+impl WorldConfig {
+    //width and height of the grid in cells
+    pub fn grid_size(&self) -> (usize, usize) {
+        (
+            (self.width / self.organism_size).ceil() as usize,
+            (self.height / self.organism_size).ceil() as usize,
+        )
+    }
+
+    pub fn cells(&self) -> usize {
+        let (width, height) = self.grid_size();
+        width * height
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PopulationConfig {
+    //number of distinct random brains in the first generation
+    pub founder_types: usize,
+    //copies of each founder in the first generation
+    pub organisms_per_founder: usize,
+}
+
+impl Default for PopulationConfig {
+    fn default() -> Self {
+        PopulationConfig {
+            founder_types: 2,
+            organisms_per_founder: 100,
+        }
+    }
+}
+
+impl PopulationConfig {
+    pub fn size(&self) -> usize {
+        self.founder_types * self.organisms_per_founder
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrainConfig {
+    //inputs of the brain in input neuron order, see sensors.rs
+    pub sensors: Vec<Sensor>,
+    //outputs of the brain in output neuron order, see actions.rs
+    pub actions: Vec<ActionNeuron>,
+    //neurons in each layer between the sensor and action layers
+    pub hidden_layers: Vec<usize>,
+    //random connections in founder brains
+    pub connections: usize,
+    //random recurrent connections (backwards, same layer or self) in founder brains, they read
+    //the activations of the previous step. Mutation only keeps recurrent genes when this isn't 0
+    pub recurrent_connections: usize,
+    //activation of each layer after the input layer (hidden layers then the action layer),
+    //empty for tanh everywhere
+    pub activations: Vec<Activation>,
+    //keep activation genes through mutation so single neurons can evolve their own activation
+    pub evolve_activations: bool,
+    //random neurons past the input layer given a bias (picked like the weights) in founder
+    //brains. 0 turns biases off, mutation only keeps bias genes while it's set
+    pub biases: usize,
+    //founder weights are picked from -weight_range..weight_range
+    pub weight_range: f32,
+    //steps per cycle of the oscillator sensor
+    pub oscillator_period: usize,
+    //radius in cells of the density and genetic similarity sensors
+    pub neighbourhood_radius: usize,
+}
+
+impl Default for BrainConfig {
+    fn default() -> Self {
+        BrainConfig {
+            sensors: vec![Sensor::LocationX, Sensor::LocationY],
+            actions: vec![ActionNeuron::MoveX, ActionNeuron::MoveY],
+            hidden_layers: vec![1],
+            connections: 5,
+            recurrent_connections: 0,
+            activations: Vec::new(),
+            evolve_activations: false,
+            biases: 0,
+            weight_range: WEIGHT_RANGE,
+            oscillator_period: 30,
+            neighbourhood_radius: 2,
+        }
+    }
+}
+
+impl BrainConfig {
+    pub fn definition(&self) -> Vec<usize> {
+        let mut definition = vec![self.sensors.len()];
+        definition.extend(&self.hidden_layers);
+        definition.push(self.actions.len());
+        definition
+    }
+
+    //the kinds of genes mutation keeps
+    pub fn gene_kinds(&self) -> GeneKinds {
+        GeneKinds {
+            recurrent: self.recurrent_connections > 0,
+            activations: self.evolve_activations,
+            biases: self.biases > 0,
+        }
+    }
+
+    pub fn random_brain(&self, rng: &mut impl Rng) -> NeuralNet {
+        let mut brain = NeuralNet::new(self.definition());
+        brain.set_layer_activations(&self.activations);
+        brain.init_random_connections(
+            self.connections,
+            (-self.weight_range, self.weight_range),
+            rng,
+        );
+        if self.recurrent_connections > 0 {
+            brain.init_random_recurrent_connections(
+                self.recurrent_connections,
+                (-self.weight_range, self.weight_range),
+                rng,
+            );
+        }
+        if self.biases > 0 {
+            brain.init_random_biases(self.biases, (-self.weight_range, self.weight_range), rng);
+        }
+        brain
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationConfig {
+    pub steps: usize,
+    pub reproduction: Reproduction,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        GenerationConfig {
+            steps: 300,
+            reproduction: Reproduction::Asexual,
+        }
+    }
+}
+
+//organisms spend energy every step and every move, and gain it by moving onto food. An
+//organism without energy starves
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergyConfig {
+    pub enabled: bool,
+    //energy of newly spawned organisms
+    pub initial: f32,
+    pub max: f32,
+    pub step_cost: f32,
+    pub move_cost: f32,
+    //energy gained from eating a food cell
+    pub food_energy: f32,
+    //share of the cells holding food at the start
+    pub initial_food: f32,
+    //share of the cells that grow food every step
+    pub regrowth: f32,
+    //food stops growing once this share of the cells holds food
+    pub max_food: f32,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        EnergyConfig {
+            enabled: false,
+            initial: 100.0,
+            max: 200.0,
+            step_cost: 0.5,
+            move_cost: 0.5,
+            food_energy: 30.0,
+            initial_food: 0.05,
+            regrowth: 0.001,
+            max_food: 0.1,
+        }
+    }
+}
+
+//files the per generation statistics are written to, see stats.rs. Nothing is recorded
+//without a path
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    pub csv: Option<PathBuf>,
+    //one json object per line
+    pub jsonl: Option<PathBuf>,
+    //species timeline, a row per living species every generation plus a row for every
+    //extinction
+    pub species: Option<PathBuf>,
+}
+
+//organisms are sorted into species by genome distance at the start of every generation,
+//see species.rs
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesConfig {
+    pub enabled: bool,
+    //largest genome distance (0 - 1) to a species' representative that still joins it
+    pub threshold: f32,
+    //colour organisms by species instead of by genome
+    pub color_by_species: bool,
+}
+
+impl Default for SpeciesConfig {
+    fn default() -> Self {
+        SpeciesConfig {
+            enabled: false,
+            threshold: 0.2,
+            color_by_species: false,
+        }
+    }
+}
+
+//the built in selection criteria, see selection.rs
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SelectionConfig {
+    #[default]
+    EastHalf,
+    Alive,
+    InsideCircle {
+        center: Vec2,
+        radius: f32,
+    },
+    InsideRectangle {
+        min: Vec2,
+        max: Vec2,
+    },
+    NearWalls {
+        distance: usize,
+    },
+    InCorners {
+        size: usize,
+    },
+}
+
+impl SelectionConfig {
+    pub fn criterion(&self) -> Box<dyn SelectionCriterion> {
+        match *self {
+            SelectionConfig::EastHalf => Box::new(EastHalf),
+            SelectionConfig::Alive => Box::new(Alive),
+            SelectionConfig::InsideCircle { center, radius } => {
+                Box::new(InsideCircle { center, radius })
+            }
+            SelectionConfig::InsideRectangle { min, max } => Box::new(InsideRectangle { min, max }),
+            SelectionConfig::NearWalls { distance } => Box::new(NearWalls { distance }),
+            SelectionConfig::InCorners { size } => Box::new(InCorners { size }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(toml::de::Error),
+    InvalidOverride(String),
+    Invalid(String),
+    Map(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Parse(error) => write!(f, "{}", error),
+            ConfigError::InvalidOverride(over) => {
+                write!(f, "invalid override {}, expected key=value", over)
+            }
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+            ConfigError::Map(path, reason) => {
+                write!(f, "invalid obstacle map {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+//sets a dotted key (e.g. world.width) in the table, the value is parsed as toml and falls
+//back to a plain string
+fn apply_override(table: &mut toml::Table, over: &str) -> Result<(), ConfigError> {
+    let invalid = || ConfigError::InvalidOverride(over.to_string());
+    let (key, value) = over.split_once('=').ok_or_else(invalid)?;
+    let value = match toml::from_str::<toml::Table>(&format!("value = {}", value)) {
+        Ok(mut parsed) => parsed.remove("value").ok_or_else(invalid)?,
+        Err(_) => toml::Value::String(value.to_string()),
+    };
+
+    let mut path: Vec<&str> = key.trim().split('.').collect();
+    let last = path
+        .pop()
+        .filter(|last| !last.is_empty())
+        .ok_or_else(invalid)?;
+    let mut table = table;
+    for section in path {
+        table = table
+            .entry(section)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(invalid)?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+impl Config {
+    //reads the config file (or the defaults when there is none), applies the key=value
+    //overrides and validates the result
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> Result<Config, ConfigError> {
+        let mut table = match path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|error| ConfigError::Io(path.to_path_buf(), error))?
+                .parse::<toml::Table>()
+                .map_err(ConfigError::Parse)?,
+            None => toml::Table::new(),
+        };
+        for over in overrides {
+            apply_override(&mut table, over)?;
+        }
+
+        let mut config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(ConfigError::Parse)?;
+
+        //the map path is relative to the config file
+        if let Some(map) = &config.obstacles.map {
+            let map = match path.and_then(Path::parent) {
+                Some(directory) => directory.join(map),
+                None => map.clone(),
+            };
+            let loaded = ObstacleMap::load(&map).map_err(|reason| ConfigError::Map(map, reason))?;
+            config.obstacles.loaded_map = Some(loaded);
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: String| Err(ConfigError::Invalid(reason));
+
+        if !(self.simulation.step_interval > 0.0) {
+            return invalid(String::from("simulation.step_interval must be positive"));
+        }
+
+        let world = &self.world;
+        if !(world.width > 0.0 && world.height > 0.0 && world.organism_size > 0.0) {
+            return invalid(String::from(
+                "world.width, world.height and world.organism_size must be positive",
+            ));
+        }
+        if !(0.0..=1.0).contains(&world.pheromone_decay) {
+            return invalid(String::from(
+                "world.pheromone_decay must be between 0 and 1",
+            ));
+        }
+
+        let population = self.population.size();
+        if population == 0 {
+            return invalid(String::from(
+                "population.founder_types and population.organisms_per_founder must be at least 1",
+            ));
+        }
+        let (width, height) = world.grid_size();
+        let obstacles = self.obstacles.grid(width, height);
+        let free_cells = world.cells() - obstacles.data.iter().filter(|cell| **cell).count();
+        if population > free_cells {
+            return invalid(format!(
+                "a population of {} doesn't fit in the {} free cells of the world",
+                population, free_cells
+            ));
+        }
+
+        let sensors = &self.brain.sensors;
+        if !(1..=MAX_PACKED_INDEX).contains(&sensors.len()) {
+            return invalid(format!(
+                "brain.sensors needs 1 to {} sensors to fit in a gene",
+                MAX_PACKED_INDEX
+            ));
+        }
+        if let Some(sensor) = sensors
+            .iter()
+            .enumerate()
+            .find_map(|(index, sensor)| sensors[..index].contains(sensor).then_some(sensor))
+        {
+            return invalid(format!("brain.sensors lists {} twice", sensor.name()));
+        }
+        let actions = &self.brain.actions;
+        if !(1..=MAX_PACKED_INDEX).contains(&actions.len()) {
+            return invalid(format!(
+                "brain.actions needs 1 to {} action neurons to fit in a gene",
+                MAX_PACKED_INDEX
+            ));
+        }
+        if let Some(action) = actions
+            .iter()
+            .enumerate()
+            .find_map(|(index, action)| actions[..index].contains(action).then_some(action))
+        {
+            return invalid(format!("brain.actions lists {} twice", action.name()));
+        }
+        if self.brain.oscillator_period == 0 {
+            return invalid(String::from("brain.oscillator_period must be at least 1"));
+        }
+
+        //layer and neuron indices are stored in 4 bits each in a packed connection
+        let definition = self.brain.definition();
+        if definition.len() > MAX_PACKED_INDEX {
+            return invalid(format!(
+                "brain.hidden_layers has {} layers, at most {} fit in a gene",
+                self.brain.hidden_layers.len(),
+                MAX_PACKED_INDEX - 2
+            ));
+        }
+        if let Some(size) = self
+            .brain
+            .hidden_layers
+            .iter()
+            .find(|size| !(1..=MAX_PACKED_INDEX).contains(*size))
+        {
+            return invalid(format!(
+                "brain.hidden_layers has a layer of {} neurons, layers need 1 to {} neurons to fit in a gene",
+                size, MAX_PACKED_INDEX
+            ));
+        }
+        if !self.brain.activations.is_empty()
+            && self.brain.activations.len() != definition.len() - 1
+        {
+            return invalid(format!(
+                "brain.activations has {} entries but the brain has {} layers after the input layer",
+                self.brain.activations.len(),
+                definition.len() - 1
+            ));
+        }
+        let brain = NeuralNet::new(definition);
+        let max_connections = brain.max_connections();
+        if self.brain.connections > max_connections {
+            return invalid(format!(
+                "brain.connections is {} but the brain only has room for {}",
+                self.brain.connections, max_connections
+            ));
+        }
+        let max_recurrent_connections = brain.max_recurrent_connections();
+        if self.brain.recurrent_connections > max_recurrent_connections {
+            return invalid(format!(
+                "brain.recurrent_connections is {} but the brain only has room for {}",
+                self.brain.recurrent_connections, max_recurrent_connections
+            ));
+        }
+        let max_biases = brain.definition().iter().skip(1).sum::<usize>();
+        if self.brain.biases > max_biases {
+            return invalid(format!(
+                "brain.biases is {} but the brain only has {} neurons past the input layer",
+                self.brain.biases, max_biases
+            ));
+        }
+        //weights are stored as thousandths in an i16
+        let max_weight = i16::MAX as f32 / 1000.0;
+        if !(self.brain.weight_range > 0.0 && self.brain.weight_range <= max_weight) {
+            return invalid(format!(
+                "brain.weight_range must be between 0 and {}",
+                max_weight
+            ));
+        }
+
+        let rates = &self.mutation;
+        let probabilities = [
+            ("bit_flip", rates.bit_flip),
+            ("weight_perturbation", rates.weight_perturbation),
+            ("duplication", rates.duplication),
+            ("deletion", rates.deletion),
+        ];
+        if let Some((name, _)) = probabilities
+            .iter()
+            .find(|(_, rate)| !(0.0..=1.0).contains(rate))
+        {
+            return invalid(format!("mutation.{} must be between 0 and 1", name));
+        }
+        if !(rates.weight_perturbation_size >= 0.0) {
+            return invalid(String::from(
+                "mutation.weight_perturbation_size can't be negative",
+            ));
+        }
+
+        if self.generation.steps == 0 {
+            return invalid(String::from("generation.steps must be at least 1"));
+        }
+        if let Reproduction::Sexual { mate_radius: 0, .. } = self.generation.reproduction {
+            return invalid(String::from(
+                "generation.reproduction.mate_radius must be at least 1",
+            ));
+        }
+
+        let valid_selection = match self.selection {
+            SelectionConfig::EastHalf | SelectionConfig::Alive => true,
+            SelectionConfig::InsideCircle { radius, .. } => radius > 0.0,
+            SelectionConfig::InsideRectangle { min, max } => min.x <= max.x && min.y <= max.y,
+            SelectionConfig::NearWalls { distance } => distance > 0,
+            SelectionConfig::InCorners { size } => size > 0,
+        };
+        if !valid_selection {
+            return invalid(format!(
+                "selection {:?} doesn't cover any cells",
+                self.selection
+            ));
+        }
+        //the zones on opposite sides of the grid mustn't overlap
+        let fits = match self.selection {
+            SelectionConfig::NearWalls { distance: extent }
+            | SelectionConfig::InCorners { size: extent } => extent * 2 <= width.min(height),
+            _ => true,
+        };
+        if !fits {
+            return invalid(format!(
+                "selection {:?} doesn't fit the {} x {} grid",
+                self.selection, width, height
+            ));
+        }
+
+        let species = &self.species;
+        if !(0.0..=1.0).contains(&species.threshold) {
+            return invalid(String::from("species.threshold must be between 0 and 1"));
+        }
+        if (species.color_by_species || self.stats.species.is_some()) && !species.enabled {
+            return invalid(String::from(
+                "species.color_by_species and stats.species need species.enabled",
+            ));
+        }
+
+        let energy = &self.energy;
+        if energy.enabled {
+            if !(energy.initial > 0.0 && energy.initial <= energy.max) {
+                return invalid(String::from(
+                    "energy.initial must be positive and at most energy.max",
+                ));
+            }
+            if !(energy.step_cost >= 0.0 && energy.move_cost >= 0.0 && energy.food_energy >= 0.0) {
+                return invalid(String::from(
+                    "energy.step_cost, energy.move_cost and energy.food_energy can't be negative",
+                ));
+            }
+            let shares = [
+                ("initial_food", energy.initial_food),
+                ("regrowth", energy.regrowth),
+                ("max_food", energy.max_food),
+            ];
+            if let Some((name, _)) = shares
+                .iter()
+                .find(|(_, share)| !(0.0..=1.0).contains(share))
+            {
+                return invalid(format!("energy.{} must be between 0 and 1", name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::crossover::CrossoverMode;
+
+    fn load(text: &str, overrides: &[&str]) -> Result<Config, ConfigError> {
+        use std::hash::{Hash, Hasher};
+        //one file per config so tests can run in parallel
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (text, overrides).hash(&mut hasher);
+        let path = std::env::temp_dir().join(format!("evo_config_{}.toml", hasher.finish()));
+        fs::write(&path, text).unwrap();
+        let overrides: Vec<String> = overrides.iter().map(|over| over.to_string()).collect();
+        let config = Config::load(Some(&path), &overrides);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn defaults() {
+        assert!(Config::load(None, &[]).unwrap() == Config::default());
+        assert!(Config::default().brain.definition() == vec![2, 1, 2]);
+        assert!(Config::default().population.size() == 200);
+    }
+
+    #[test]
+    fn load_file() {
+        let config = load(
+            "[world]\nwidth = 200.0\n\n[brain]\nhidden_layers = [3, 4]\nactivations = [\"relu\", \"leaky_relu\", \"tanh\"]\nsensors = [\"age\", \"oscillator\", \"density\"]\nactions = [\"move_forward\", \"turn_left\", \"turn_right\", \"kill\"]\n\n\
+             [generation.reproduction]\nkind = \"sexual\"\ncrossover = \"uniform\"\nmate_radius = 3\n\n\
+             [selection]\nkind = \"inside_circle\"\ncenter = [10.0, 20.0]\nradius = 5.0\n",
+            &[],
+        )
+        .unwrap();
+        assert!(config.world.width == 200.0);
+        assert!(config.world.height == 400.0);
+        assert!(config.brain.definition() == vec![3, 3, 4, 4]);
+        assert!(
+            config.brain.activations
+                == vec![Activation::Relu, Activation::LeakyRelu, Activation::Tanh]
+        );
+        assert!(
+            config.generation.reproduction
+                == Reproduction::Sexual {
+                    crossover: CrossoverMode::Uniform,
+                    mate_radius: 3
+                }
+        );
+        assert!(
+            config.selection
+                == SelectionConfig::InsideCircle {
+                    center: Vec2::new(10.0, 20.0),
+                    radius: 5.0
+                }
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let config = load(
+            "[world]\nwidth = 200.0\n",
+            &[
+                "world.width=300",
+                "brain.hidden_layers=[2,2]",
+                "selection.kind=near_walls",
+                "selection.distance=4",
+            ],
+        )
+        .unwrap();
+        assert!(config.world.width == 300.0);
+        assert!(config.brain.hidden_layers == vec![2, 2]);
+        assert!(config.selection == SelectionConfig::NearWalls { distance: 4 });
+
+        assert!(matches!(
+            load("", &["world.width"]),
+            Err(ConfigError::InvalidOverride(_))
+        ));
+        assert!(matches!(
+            load("", &["world.width=300", "world.width.x=1"]),
+            Err(ConfigError::InvalidOverride(_))
+        ));
+    }
+
+    #[test]
+    fn reject_invalid_configs() {
+        let invalid = |text: &str| matches!(load(text, &[]), Err(ConfigError::Invalid(_)));
+        assert!(invalid("[brain]\nhidden_layers = [17]\n"));
+        assert!(invalid("[brain]\nsensors = []\n"));
+        assert!(invalid("[brain]\nactions = [\"kill\", \"kill\"]\n"));
+        assert!(invalid(
+            "[brain]\nsensors = [\"age\", \"random\", \"age\"]\n"
+        ));
+        assert!(invalid("[brain]\nhidden_layers = [0]\n"));
+        assert!(invalid(
+            "[brain]\nhidden_layers = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]\n"
+        ));
+        assert!(invalid("[brain]\nconnections = 100\n"));
+        assert!(invalid("[brain]\nrecurrent_connections = 100\n"));
+        assert!(invalid("[brain]\nbiases = 4\n"));
+        assert!(invalid("[brain]\nactivations = [\"relu\"]\n"));
+        assert!(invalid("[brain]\nweight_range = 40.0\n"));
+        assert!(invalid("[mutation]\nbit_flip = 1.5\n"));
+        assert!(invalid("[population]\norganisms_per_founder = 4000\n"));
+        assert!(invalid("[generation]\nsteps = 0\n"));
+        //the default grid is 80 x 80
+        assert!(invalid(
+            "[selection]\nkind = \"near_walls\"\ndistance = 41\n"
+        ));
+        assert!(load("[selection]\nkind = \"near_walls\"\ndistance = 40\n", &[]).is_ok());
+        assert!(invalid("[selection]\nkind = \"in_corners\"\nsize = 41\n"));
+        assert!(invalid("[energy]\nenabled = true\ninitial = 300.0\n"));
+        assert!(invalid("[energy]\nenabled = true\nregrowth = 2.0\n"));
+        assert!(load("[energy]\nregrowth = 2.0\n", &[]).is_ok());
+        assert!(invalid("[world]\norganism_size = 0.0\n"));
+        assert!(invalid("[species]\nthreshold = 1.5\n"));
+        assert!(invalid("[species]\ncolor_by_species = true\n"));
+        //an 80 x 80 grid with only 100 free cells left for 200 organisms
+        assert!(invalid(
+            "[obstacles]\nrectangles = [{ min = [0.0, 0.0], max = [79.0, 78.0] }, { min = [0.0, 79.0], max = [59.0, 79.0] }]\n"
+        ));
+        assert!(matches!(
+            load("[obstacles]\nmap = \"missing_map.txt\"\n", &[]),
+            Err(ConfigError::Map(..))
+        ));
+
+        assert!(matches!(
+            load("[world]\nwidht = 300.0\n", &[]),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            load("[brain]\nhidden_layers = 3\n", &[]),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            Config::load(Some(Path::new("missing.toml")), &[]),
+            Err(ConfigError::Io(..))
+        ));
+    }
+}
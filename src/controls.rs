@@ -0,0 +1,348 @@
+use crate::generation::{Generation, GenerationLimit};
+use bevy::{
+    color::palettes::{
+        css::{BLACK, WHITE},
+        tailwind::{GRAY_300, GRAY_400, GRAY_500},
+    },
+    ecs::schedule::ScheduleLabel,
+    prelude::*,
+};
+use std::time::{Duration, Instant};
+
+//faster than this the simulation runs as many steps as fit in a frame
+const MIN_STEP_INTERVAL: f32 = 0.001;
+const MAX_STEP_INTERVAL: f32 = 2.0;
+//time spent stepping per frame when running as fast as possible, leaves time to draw
+const FRAME_BUDGET: Duration = Duration::from_millis(12);
+
+//one step of the simulation, run from the Update schedule as often as the speed asks for
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SimulationStep;
+
+//the system running SimulationStep in Update, anything reacting to the steps of the frame
+//goes after it
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Simulation;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advance {
+    Step,
+    //until the generation count reaches the target
+    Generation(usize),
+}
+
+#[derive(Resource)]
+pub struct SimulationSpeed {
+    //a step every time the timer finishes
+    pub timer: Timer,
+    pub paused: bool,
+    //as many steps per frame as fit in the frame budget, ignores the timer
+    pub unlimited: bool,
+    //steps requested while paused
+    pub advance: Option<Advance>,
+}
+
+impl SimulationSpeed {
+    pub fn new(speed: f32) -> Self {
+        SimulationSpeed {
+            timer: Timer::from_seconds(speed, TimerMode::Repeating),
+            paused: false,
+            unlimited: false,
+            advance: None,
+        }
+    }
+
+    //halves the step interval, below the minimum the speed becomes unlimited
+    pub fn faster(&mut self) {
+        let interval = self.timer.duration().as_secs_f32() / 2.0;
+        if interval < MIN_STEP_INTERVAL {
+            self.unlimited = true;
+        } else {
+            self.timer.set_duration(Duration::from_secs_f32(interval));
+        }
+    }
+
+    pub fn slower(&mut self) {
+        if self.unlimited {
+            self.unlimited = false;
+            return;
+        }
+        let interval = (self.timer.duration().as_secs_f32() * 2.0).min(MAX_STEP_INTERVAL);
+        self.timer.set_duration(Duration::from_secs_f32(interval));
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = None;
+    }
+
+    //pauses and runs a single step
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.advance = Some(Advance::Step);
+    }
+
+    //pauses after running the rest of the current generation as fast as possible
+    pub fn next_generation(&mut self, generation: usize) {
+        self.paused = true;
+        self.advance = Some(Advance::Generation(generation + 1));
+    }
+
+    pub fn label(&self) -> String {
+        let speed = if self.unlimited {
+            String::from("unlimited")
+        } else {
+            format!("{:.0} steps/s", 1.0 / self.timer.duration().as_secs_f32())
+        };
+        match (self.paused, self.advance) {
+            (_, Some(Advance::Generation(_))) => String::from("to next generation"),
+            (true, _) => format!("paused ({})", speed),
+            (false, _) => speed,
+        }
+    }
+}
+
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        SimulationSpeed::new(0.1)
+    }
+}
+
+//the controls, as buttons of the overlay and keys
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Pause,
+    Step,
+    Generation,
+    Slower,
+    Faster,
+}
+
+impl Control {
+    const ALL: [Control; 5] = [
+        Control::Pause,
+        Control::Step,
+        Control::Generation,
+        Control::Slower,
+        Control::Faster,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Control::Pause => "pause [space]",
+            Control::Step => "step [.]",
+            Control::Generation => "generation [g]",
+            Control::Slower => "slower [-]",
+            Control::Faster => "faster [+]",
+        }
+    }
+
+    fn keys(&self) -> &'static [KeyCode] {
+        match self {
+            Control::Pause => &[KeyCode::Space],
+            Control::Step => &[KeyCode::Period],
+            Control::Generation => &[KeyCode::KeyG],
+            Control::Slower => &[KeyCode::Minus, KeyCode::NumpadSubtract],
+            Control::Faster => &[KeyCode::Equal, KeyCode::NumpadAdd],
+        }
+    }
+
+    fn apply(&self, speed: &mut SimulationSpeed, generation: &Generation) {
+        match self {
+            Control::Pause => speed.toggle_pause(),
+            Control::Step => speed.step(),
+            Control::Generation => speed.next_generation(generation.count),
+            Control::Slower => speed.slower(),
+            Control::Faster => speed.faster(),
+        }
+    }
+}
+
+pub fn tick_simulation_timer(time: Res<Time>, mut simulation_speed: ResMut<SimulationSpeed>) {
+    if !simulation_speed.paused {
+        simulation_speed.timer.tick(time.delta());
+    }
+}
+
+//runs the steps due this frame, stops at the generation limit so it isn't overshot
+pub fn run_simulation(world: &mut World) {
+    let started = Instant::now();
+    let limit = world.get_resource::<GenerationLimit>().map(|limit| limit.0);
+    let limit_reached =
+        |world: &World| limit.is_some_and(|limit| world.resource::<Generation>().count >= limit);
+
+    let mut speed = world.resource_mut::<SimulationSpeed>();
+    let steps = match speed.advance {
+        Some(Advance::Step) => {
+            speed.advance = None;
+            1
+        }
+        Some(Advance::Generation(_)) => u32::MAX,
+        None if speed.paused => 0,
+        None if speed.unlimited => u32::MAX,
+        None => speed.timer.times_finished_this_tick(),
+    };
+
+    for _ in 0..steps {
+        if limit_reached(world) {
+            break;
+        }
+        world.run_schedule(SimulationStep);
+
+        let count = world.resource::<Generation>().count;
+        let mut speed = world.resource_mut::<SimulationSpeed>();
+        if let Some(Advance::Generation(target)) = speed.advance {
+            if count >= target {
+                speed.advance = None;
+                break;
+            }
+        }
+        if started.elapsed() >= FRAME_BUDGET {
+            break;
+        }
+    }
+}
+
+//runs a single step every update, for headless runs
+pub fn step_simulation(world: &mut World) {
+    world.run_schedule(SimulationStep);
+}
+
+pub fn control_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    generation: Res<Generation>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    for control in Control::ALL {
+        if keys.any_just_pressed(control.keys().iter().copied()) {
+            control.apply(&mut speed, &generation);
+        }
+    }
+}
+
+pub fn control_buttons(
+    generation: Res<Generation>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut buttons: Query<(&Interaction, &Control, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, control, mut background) in buttons.iter_mut() {
+        *background = match interaction {
+            Interaction::Pressed => {
+                control.apply(&mut speed, &generation);
+                Color::Srgba(GRAY_500).into()
+            }
+            Interaction::Hovered => Color::Srgba(GRAY_400).into(),
+            Interaction::None => Color::Srgba(GRAY_300).into(),
+        };
+    }
+}
+
+//text of the overlay showing the speed and counters
+#[derive(Component)]
+pub struct OverlayText;
+
+//counters and speed in the top left corner, with a button per control below
+pub fn spawn_overlay(mut commands: Commands) {
+    let text_style = TextStyle {
+        font_size: 14.0,
+        color: Color::Srgba(BLACK),
+        ..default()
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|overlay| {
+            overlay.spawn((
+                TextBundle::from_section("", text_style.clone()),
+                OverlayText,
+            ));
+            overlay
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|row| {
+                    for control in Control::ALL {
+                        row.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    padding: UiRect::axes(Val::Px(6.0), Val::Px(3.0)),
+                                    ..default()
+                                },
+                                background_color: Color::Srgba(GRAY_300).into(),
+                                border_color: Color::Srgba(WHITE).into(),
+                                ..default()
+                            },
+                            control,
+                        ))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section(
+                                control.label(),
+                                text_style.clone(),
+                            ));
+                        });
+                    }
+                });
+        });
+}
+
+pub fn update_overlay(
+    generation: Res<Generation>,
+    speed: Res<SimulationSpeed>,
+    mut texts: Query<&mut Text, With<OverlayText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!(
+            "generation {}  step {}/{}\n{}",
+            generation.count,
+            generation.step,
+            generation.steps_per_generation,
+            speed.label()
+        );
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_speed() {
+        let mut speed = SimulationSpeed::new(0.01);
+        speed.slower();
+        assert!((speed.timer.duration().as_secs_f32() - 0.02).abs() < 1e-6);
+        assert!(speed.label() == "50 steps/s");
+
+        for _ in 0..10 {
+            speed.faster();
+        }
+        assert!(speed.unlimited && speed.label() == "unlimited");
+        speed.slower();
+        assert!(!speed.unlimited);
+        assert!(speed.timer.duration().as_secs_f32() >= MIN_STEP_INTERVAL);
+
+        for _ in 0..20 {
+            speed.slower();
+        }
+        assert!(speed.timer.duration() == Duration::from_secs_f32(MAX_STEP_INTERVAL));
+
+        speed.step();
+        assert!(speed.paused && speed.advance == Some(Advance::Step));
+        speed.toggle_pause();
+        assert!(!speed.paused && speed.advance.is_none());
+        speed.next_generation(3);
+        assert!(speed.advance == Some(Advance::Generation(4)));
+    }
+}
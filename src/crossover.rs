@@ -0,0 +1,134 @@
+use crate::gene::{Gene, Genome, GenomeError};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossoverMode {
+    //child takes the genes of the first parent up to a random point and the rest from the second
+    SinglePoint,
+    //child takes a random slice of the second parent's genes, the rest from the first
+    TwoPoint,
+    //every gene is picked from either parent with equal probability
+    Uniform,
+}
+
+pub fn crossover(
+    a: &Genome,
+    b: &Genome,
+    mode: CrossoverMode,
+    rng: &mut impl Rng,
+) -> Result<Genome, GenomeError> {
+    if a.layers() != b.layers() {
+        return Err(GenomeError::MismatchedLayers(
+            a.layers().to_vec(),
+            b.layers().to_vec(),
+        ));
+    }
+
+    let (a_genes, b_genes) = (a.genes(), b.genes());
+    let shortest = a_genes.len().min(b_genes.len());
+
+    let genes: Vec<Gene> = match mode {
+        CrossoverMode::SinglePoint => {
+            let point = rng.gen_range(0..=shortest);
+            a_genes[..point]
+                .iter()
+                .chain(&b_genes[point..])
+                .cloned()
+                .collect()
+        }
+        CrossoverMode::TwoPoint => {
+            let first = rng.gen_range(0..=shortest);
+            let second = rng.gen_range(first..=shortest);
+            a_genes[..first]
+                .iter()
+                .chain(&b_genes[first..second])
+                .chain(&a_genes[second..])
+                .cloned()
+                .collect()
+        }
+        CrossoverMode::Uniform => {
+            let longest = a_genes.len().max(b_genes.len());
+            (0..longest)
+                .filter_map(|index| match (a_genes.get(index), b_genes.get(index)) {
+                    (Some(a_gene), Some(b_gene)) => {
+                        Some(if rng.gen_bool(0.5) { a_gene } else { b_gene })
+                    }
+                    //genes past the end of the shorter parent are inherited half the time
+                    (Some(gene), None) | (None, Some(gene)) => rng.gen_bool(0.5).then_some(gene),
+                    (None, None) => None,
+                })
+                .cloned()
+                .collect()
+        }
+    };
+
+    Ok(Genome::from_genes(a.layers().to_vec(), genes))
+}
+
+mod tests {
+    use super::*;
+    use crate::neural::{NeuralNet, WEIGHT_RANGE};
+
+    fn random_genome(connections: usize) -> Genome {
+        let mut test_net = NeuralNet::new(vec![4, 3, 2]);
+        test_net.init_random_connections(
+            connections,
+            (-WEIGHT_RANGE, WEIGHT_RANGE),
+            &mut rand::thread_rng(),
+        );
+        test_net.into()
+    }
+
+    #[test]
+    fn single_point_crossover() {
+        let (a, b) = (random_genome(6), random_genome(9));
+        for _ in 0..20 {
+            let child =
+                crossover(&a, &b, CrossoverMode::SinglePoint, &mut rand::thread_rng()).unwrap();
+            assert!(child.length() == b.length());
+            let point = (0..=a.length()).find(|point| {
+                child.genes()[..*point] == a.genes()[..*point]
+                    && child.genes()[*point..] == b.genes()[*point..]
+            });
+            assert!(point.is_some());
+            assert!(NeuralNet::try_from(&child).is_ok());
+        }
+    }
+
+    #[test]
+    fn two_point_crossover() {
+        let (a, b) = (random_genome(9), random_genome(6));
+        for _ in 0..20 {
+            let child =
+                crossover(&a, &b, CrossoverMode::TwoPoint, &mut rand::thread_rng()).unwrap();
+            assert!(child.length() == a.length());
+            for (index, gene) in child.genes().iter().enumerate() {
+                assert!(*gene == a.genes()[index] || b.genes().get(index) == Some(gene));
+            }
+            assert!(NeuralNet::try_from(&child).is_ok());
+        }
+    }
+
+    #[test]
+    fn uniform_crossover() {
+        let (a, b) = (random_genome(8), random_genome(8));
+        for _ in 0..20 {
+            let child = crossover(&a, &b, CrossoverMode::Uniform, &mut rand::thread_rng()).unwrap();
+            assert!(child.length() == 8);
+            for (index, gene) in child.genes().iter().enumerate() {
+                assert!(*gene == a.genes()[index] || *gene == b.genes()[index]);
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_parents() {
+        let a = random_genome(4);
+        let mut test_net = NeuralNet::new(vec![4, 2]);
+        test_net.init_random_connections(4, (-WEIGHT_RANGE, WEIGHT_RANGE), &mut rand::thread_rng());
+        let b: Genome = test_net.into();
+        assert!(crossover(&a, &b, CrossoverMode::Uniform, &mut rand::thread_rng()).is_err());
+    }
+}
@@ -1,37 +1,44 @@
 use crate::{
+    config::BrainConfig,
     gene::Genome,
-    neural::{NeuralNet, WEIGHT_RANGE},
-    organism::{Action, Direction, Organism},
-    utils::{vec2_to_i32, Grid2d},
+    neural::{BrainState, CompiledNet},
+    organism::{Action, CompiledBrain, Direction, Organism},
+    utils::{bernoulli_trial, vec2_to_i32, Grid2d},
 };
 
 use bevy::prelude::*;
 
 use bevy_prototype_lyon::prelude::*;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Resource)]
-pub struct SimulationSpeed {
-    pub timer: Timer,
+//how the edges of the grid behave
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    //walls on every side
+    #[default]
+    Bounded,
+    //leaving through any side enters from the opposite side
+    Torus,
+    //wraps east to west, walls north and south
+    WrapX,
+    //wraps north to south, walls east and west
+    WrapY,
 }
-impl SimulationSpeed {
-    pub fn new(speed: f32) -> Self {
-        SimulationSpeed {
-            timer: Timer::from_seconds(speed, TimerMode::Repeating),
-        }
+
+impl Topology {
+    pub fn wraps_x(&self) -> bool {
+        matches!(self, Topology::Torus | Topology::WrapX)
     }
-}
 
-impl Default for SimulationSpeed {
-    fn default() -> Self {
-        SimulationSpeed {
-            timer: Timer::from_seconds(0.1, TimerMode::Repeating),
-        }
+    pub fn wraps_y(&self) -> bool {
+        matches!(self, Topology::Torus | Topology::WrapY)
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct Environment {
     pub width: f32,
     pub height: f32,
@@ -41,7 +48,20 @@ pub struct Environment {
     pub thickness: f32,
     pub num_organisms: usize,
     pub organism_size: f32,
-    pub organisms: Grid2d<u32>,
+    //entity bits of the organism in each cell (never 0, which marks an empty cell)
+    pub organisms: Grid2d<u64>,
+    //pheromone level of each cell (0 - 1)
+    pub pheromones: Grid2d<f32>,
+    //share of the pheromone that evaporates every step
+    pub pheromone_decay: f32,
+    //cells holding food
+    pub food: Grid2d<bool>,
+    //walls inside the environment, nothing can enter or grow on these cells
+    pub obstacles: Grid2d<bool>,
+    pub topology: Topology,
+    //skip all rendering work (shapes and transforms)
+    #[serde(skip)]
+    pub headless: bool,
 }
 
 impl Environment {
@@ -59,6 +79,9 @@ impl Environment {
             (width / organism_size).ceil() as usize,
             (height / organism_size).ceil() as usize,
         );
+        let pheromones = Grid2d::new(organisms.width, organisms.height);
+        let food = Grid2d::new(organisms.width, organisms.height);
+        let obstacles = Grid2d::new(organisms.width, organisms.height);
         Environment {
             width,
             height,
@@ -69,15 +92,172 @@ impl Environment {
             num_organisms,
             organism_size,
             organisms,
+            pheromones,
+            pheromone_decay: 0.1,
+            food,
+            obstacles,
+            topology: Topology::Bounded,
+            headless: false,
         }
     }
 
-    pub fn spawn_organism_n(&mut self, commands: &mut Commands, mut organism: Organism, n: usize) {
-        let mut rng = rand::thread_rng();
-        for _ in 0..n {
+    // Convert grid coordinates to the world coordinates of the cell center
+    pub fn cell_to_world(&self, cell: Vec2) -> Vec2 {
+        Vec2::new(
+            (cell.x * self.organism_size) + self.x - (self.width / 2.0)
+                + (self.organism_size / 2.0),
+            (cell.y * self.organism_size) + self.y - (self.height / 2.0)
+                + (self.organism_size / 2.0),
+        )
+    }
+
+    //grid cell under a world position, None outside the environment
+    pub fn world_to_cell(&self, world: Vec2) -> Option<(usize, usize)> {
+        let x = ((world.x - self.x + self.width / 2.0) / self.organism_size).floor();
+        let y = ((world.y - self.y + self.height / 2.0) / self.organism_size).floor();
+        let inside = x >= 0.0
+            && y >= 0.0
+            && x < self.organisms.width as f32
+            && y < self.organisms.height as f32;
+        inside.then_some((x as usize, y as usize))
+    }
+
+    //picks a random unoccupied grid cell, None if the grid is full
+    pub fn random_empty_cell(&self, rng: &mut impl Rng) -> Option<(usize, usize)> {
+        let full = self
+            .organisms
+            .data
+            .iter()
+            .zip(&self.obstacles.data)
+            .all(|(id, obstacle)| *id != 0 || *obstacle);
+        if full {
+            return None;
+        }
+
+        loop {
             let grid_x = rng.gen_range(0..self.organisms.width);
             let grid_y = rng.gen_range(0..self.organisms.height);
+            if self.organisms.get(grid_x, grid_y).is_none()
+                && self.obstacles.get(grid_x, grid_y).is_none()
+            {
+                return Some((grid_x, grid_y));
+            }
+        }
+    }
+
+    //the grid cell at x, y after wrapping around the wrapped edges, None if it lies beyond a
+    //bounded edge
+    pub fn wrap(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let x = wrap_axis(x, self.organisms.width, self.topology.wraps_x())?;
+        let y = wrap_axis(y, self.organisms.height, self.topology.wraps_y())?;
+        Some((x, y))
+    }
+
+    //shortest offset from one cell to another, possibly across a wrapped edge
+    pub fn offset(&self, from: (usize, usize), to: (usize, usize)) -> (i32, i32) {
+        (
+            axis_offset(from.0, to.0, self.organisms.width, self.topology.wraps_x()),
+            axis_offset(from.1, to.1, self.organisms.height, self.topology.wraps_y()),
+        )
+    }
+
+    //whether an organism can stand on the cell: inside the grid and not an obstacle
+    pub fn is_passable(&self, x: i32, y: i32) -> bool {
+        self.wrap(x, y)
+            .is_some_and(|(x, y)| self.obstacles.get(x, y).is_none())
+    }
+
+    //every cell within `radius` cells of position (including position), each cell once
+    pub fn neighbourhood(&self, position: (usize, usize), radius: usize) -> Vec<(usize, usize)> {
+        let radius = radius as i32;
+        let mut cells = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let Some(cell) = self.wrap(position.0 as i32 + dx, position.1 as i32 + dy) else {
+                    continue;
+                };
+                //a radius wider than a wrapped world reaches the same cell twice
+                if !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+        }
+        cells
+    }
+
+    //occupants of the cells within `radius` cells of position, excluding position itself
+    pub fn neighbours(
+        &self,
+        position: (usize, usize),
+        radius: usize,
+    ) -> Vec<((usize, usize), u64)> {
+        self.neighbourhood(position, radius)
+            .into_iter()
+            .filter(|cell| *cell != position)
+            .filter_map(|(x, y)| self.organisms.get(x, y).map(|id| ((x, y), *id)))
+            .collect()
+    }
+
+    //whether the neighbouring cell in the direction is outside the grid, a wall or occupied
+    pub fn blocked(&self, position: (usize, usize), direction: Direction) -> bool {
+        let (dx, dy) = direction.offset();
+        match self.wrap(position.0 as i32 + dx, position.1 as i32 + dy) {
+            Some((x, y)) => {
+                self.obstacles.get(x, y).is_some() || self.organisms.get(x, y).is_some()
+            }
+            None => true,
+        }
+    }
+
+    //grows food on `share` of the cells at random, without covering more than `max_share`
+    pub fn grow_food(&mut self, share: f32, max_share: f32, rng: &mut impl Rng) {
+        let cells = self.food.data.len();
+        let limit = (max_share * cells as f32) as usize;
+        let mut food = self.food.data.iter().filter(|food| **food).count();
+
+        let expected = share * cells as f32;
+        let mut grow = expected as usize;
+        if bernoulli_trial(expected.fract(), rng) {
+            grow += 1;
+        }
+
+        for _ in 0..grow {
+            if food >= limit {
+                break;
+            }
+            let x = rng.gen_range(0..self.food.width);
+            let y = rng.gen_range(0..self.food.height);
+            if self.food.get(x, y).is_none() && self.obstacles.get(x, y).is_none() {
+                self.food.set(x, y, true);
+                food += 1;
+            }
+        }
+    }
+
+    pub fn decay_pheromones(&mut self) {
+        let decay = self.pheromone_decay;
+        for level in self.pheromones.data.iter_mut() {
+            *level *= 1.0 - decay;
+            //let faint trails disappear completely
+            if *level < 0.01 {
+                *level = 0.0;
+            }
+        }
+    }
+
+    pub fn spawn_organism_n(
+        &mut self,
+        commands: &mut Commands,
+        mut organism: Organism,
+        n: usize,
+        rng: &mut impl Rng,
+    ) {
+        for _ in 0..n {
+            let Some((grid_x, grid_y)) = self.random_empty_cell(rng) else {
+                break;
+            };
             organism.position = Vec2::new(grid_x as f32, grid_y as f32);
+            organism.heading = Direction::random(rng);
             self.spawn_organism(commands, organism.clone(), (grid_x, grid_y));
         }
     }
@@ -88,23 +268,37 @@ impl Environment {
         organism: Organism,
         position: (usize, usize),
     ) {
+        let brain = CompiledBrain(CompiledNet::from(&organism.brain));
+        if self.headless {
+            let organism_entity = commands.spawn((organism, brain));
+            self.organisms
+                .set(position.0, position.1, organism_entity.id().to_bits());
+            return;
+        }
+
         let organism_color = organism.genome.get_color();
+        let rotation = heading_rotation(organism.heading);
 
-        // Convert grid coordinates to world coordinates
-        let world_x = (position.0 as f32 * self.organism_size) + self.x - (self.width / 2.0)
-            + (self.organism_size / 2.0);
-        let world_y = (position.1 as f32 * self.organism_size) + self.y - (self.height / 2.0)
-            + (self.organism_size / 2.0);
+        let world = self.cell_to_world(Vec2::new(position.0 as f32, position.1 as f32));
+        let (world_x, world_y) = (world.x, world.y);
 
+        //a triangle pointing north, rotated to the heading
+        let half = self.organism_size / 2.0;
         let organism_entity = commands.spawn((
             organism,
+            brain,
             ShapeBundle {
-                path: GeometryBuilder::build_as(&shapes::Rectangle {
-                    extents: Vec2::splat(self.organism_size),
-                    ..shapes::Rectangle::default()
+                path: GeometryBuilder::build_as(&shapes::Polygon {
+                    points: vec![
+                        Vec2::new(0.0, half),
+                        Vec2::new(half, -half),
+                        Vec2::new(-half, -half),
+                    ],
+                    closed: true,
                 }),
                 spatial: SpatialBundle {
-                    transform: Transform::from_translation(Vec3::new(world_x, world_y, 1.0)),
+                    transform: Transform::from_translation(Vec3::new(world_x, world_y, 1.0))
+                        .with_rotation(rotation),
                     ..default()
                 },
                 ..default()
@@ -117,25 +311,109 @@ impl Environment {
         ));
 
         self.organisms
-            .set(position.0, position.1, organism_entity.id().index());
+            .set(position.0, position.1, organism_entity.id().to_bits());
     }
 
-    pub fn spawn_n_random_organisms(&mut self, commands: &mut Commands, n: usize) {
-        let mut rng = rand::thread_rng();
+    pub fn spawn_n_random_organisms(
+        &mut self,
+        commands: &mut Commands,
+        n: usize,
+        brain: &BrainConfig,
+        energy: f32,
+        rng: &mut impl Rng,
+    ) {
         for _ in 0..(n) {
-            let grid_x = rng.gen_range(0..self.organisms.width);
-            let grid_y = rng.gen_range(0..self.organisms.height);
+            let Some((grid_x, grid_y)) = self.random_empty_cell(rng) else {
+                break;
+            };
 
-            let mut organism_brain = NeuralNet::new(vec![2, 1, 2]);
-            organism_brain.init_random_connections(4, (-WEIGHT_RANGE, WEIGHT_RANGE));
+            let organism_brain = brain.random_brain(rng);
             let organism_genome: Genome = organism_brain.clone().into();
             let organism = Organism {
                 brain: organism_brain,
                 genome: organism_genome,
                 position: Vec2::new(grid_x as f32, grid_y as f32),
+                heading: Direction::random(rng),
+                last_move: None,
+                oscillator_period: None,
+                energy,
+                species: None,
+                memory: BrainState::default(),
             };
 
             self.spawn_organism(commands, organism, (grid_x, grid_y))
         }
     }
 }
+
+fn wrap_axis(value: i32, size: usize, wraps: bool) -> Option<usize> {
+    if wraps {
+        Some(value.rem_euclid(size as i32) as usize)
+    } else if value >= 0 && value < size as i32 {
+        Some(value as usize)
+    } else {
+        None
+    }
+}
+
+fn axis_offset(from: usize, to: usize, size: usize, wraps: bool) -> i32 {
+    let offset = to as i32 - from as i32;
+    if wraps && offset.abs() * 2 > size as i32 {
+        offset - offset.signum() * size as i32
+    } else {
+        offset
+    }
+}
+
+//rotation of an organism's shape facing the direction
+pub fn heading_rotation(direction: Direction) -> Quat {
+    Quat::from_rotation_z(-direction.angle())
+}
+
+mod tests {
+    use super::*;
+    use bevy::color::palettes::css::BLACK;
+
+    #[test]
+    fn wrap_around_edges() {
+        // 10 x 10 grid
+        let mut environment =
+            Environment::new(50.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        environment.organisms.set(9, 0, 1);
+
+        assert!(environment.wrap(-1, 3).is_none());
+        assert!(environment.blocked((0, 0), Direction::West));
+        assert!(environment.neighbours((0, 0), 1).is_empty());
+        assert!(environment.offset((0, 0), (9, 0)) == (9, 0));
+
+        environment.topology = Topology::Torus;
+        assert!(environment.wrap(-1, 10) == Some((9, 0)));
+        assert!(environment.blocked((0, 0), Direction::West));
+        assert!(!environment.blocked((0, 0), Direction::South));
+        assert!(environment.neighbours((0, 0), 1) == vec![((9, 0), 1)]);
+        assert!(environment.offset((0, 0), (9, 9)) == (-1, -1));
+        assert!(environment.neighbourhood((0, 0), 1).len() == 9);
+        //a radius covering the whole world counts every cell once
+        assert!(environment.neighbourhood((0, 0), 6).len() == 100);
+
+        environment.topology = Topology::WrapX;
+        assert!(environment.wrap(-1, 0) == Some((9, 0)));
+        assert!(environment.wrap(0, -1).is_none());
+        environment.topology = Topology::WrapY;
+        assert!(environment.wrap(-1, 0).is_none());
+        assert!(environment.wrap(0, -1) == Some((0, 9)));
+    }
+
+    #[test]
+    fn world_to_cell() {
+        let environment = Environment::new(50.0, 40.0, 10.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        for cell in [(0, 0), (3, 7), (9, 7)] {
+            let world = environment.cell_to_world(Vec2::new(cell.0 as f32, cell.1 as f32));
+            assert!(environment.world_to_cell(world) == Some(cell));
+            //anywhere inside the cell
+            assert!(environment.world_to_cell(world + Vec2::new(2.4, -2.4)) == Some(cell));
+        }
+        assert!(environment.world_to_cell(Vec2::new(-15.1, 0.0)).is_none());
+        assert!(environment.world_to_cell(Vec2::new(0.0, 20.0)).is_none());
+    }
+}
@@ -1,5 +1,47 @@
-use crate::neural::{ConnectionPacked, NeuralNet, WEIGHT_RANGE};
-use std::f32::consts::PI;
+use crate::neural::{Connection, ConnectionPacked, NeuralNet, MAX_PACKED_INDEX, WEIGHT_RANGE};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, fmt};
+
+#[derive(Debug, PartialEq)]
+pub enum GenomeError {
+    InvalidHex(String),
+    InvalidLength(String),
+    InvalidDefinition(Vec<usize>),
+    InvalidConnection(String),
+    MismatchedLayers(Vec<usize>, Vec<usize>),
+}
+
+impl fmt::Display for GenomeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenomeError::InvalidHex(gene) => write!(f, "gene {} is not valid hex", gene),
+            GenomeError::InvalidLength(gene) => write!(f, "gene {} is not 4 bytes long", gene),
+            GenomeError::InvalidDefinition(definition) => write!(
+                f,
+                "layer definition {:?} must have 2 to {} layers of 1 to {} neurons",
+                definition, MAX_PACKED_INDEX, MAX_PACKED_INDEX
+            ),
+            GenomeError::InvalidConnection(gene) => {
+                write!(
+                    f,
+                    "gene {} does not connect two neurons of the network",
+                    gene
+                )
+            }
+            GenomeError::MismatchedLayers(a, b) => {
+                write!(
+                    f,
+                    "genomes with layers {:?} and {:?} can't be combined",
+                    a, b
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GenomeError {}
 
 pub struct HSLColor {
     pub hue: u16,
@@ -38,8 +80,10 @@ impl HSLColor {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Genome {
+    //number of neurons in each layer of the network the genes describe
+    layers: Vec<usize>,
     genes: Vec<Gene>,
     //traits are an attempt at summarizing the organisms behaviour(taking direct
     //input and output connections, only the traits are used in coloring)
@@ -47,14 +91,62 @@ pub struct Genome {
 }
 
 impl Genome {
-    pub fn new(genes: Vec<Gene>, traits: Vec<Gene>) -> Self {
-        Genome { genes, traits }
+    pub fn new(layers: Vec<usize>, genes: Vec<Gene>, traits: Vec<Gene>) -> Self {
+        Genome {
+            layers,
+            genes,
+            traits,
+        }
+    }
+
+    //builds a genome from raw genes, keeping their order and deriving the traits from
+    //the direct input to output connections
+    pub fn from_genes(layers: Vec<usize>, genes: Vec<Gene>) -> Self {
+        let last_layer_index = layers.len().saturating_sub(1);
+        let traits = genes
+            .iter()
+            .filter(|gene| {
+                ConnectionPacked::try_from(*gene).is_ok_and(|packed| {
+                    packed.get_from().0 == 0 && packed.get_to().0 == last_layer_index
+                })
+            })
+            .cloned()
+            .collect();
+        Genome {
+            layers,
+            genes,
+            traits,
+        }
     }
 
     pub fn length(&self) -> usize {
         self.genes.len()
     }
 
+    pub fn layers(&self) -> &[usize] {
+        &self.layers
+    }
+
+    pub fn genes(&self) -> &[Gene] {
+        &self.genes
+    }
+
+    //share of matching bits between the genes at the same index (0 - 1), genes without a
+    //counterpart count as completely different
+    pub fn similarity(&self, other: &Genome) -> f32 {
+        bit_similarity(&self.gene_bits(), &other.gene_bits())
+    }
+
+    //share of differing bits between the genes at the same index (0 - 1), the hamming
+    //distance counterpart of similarity
+    pub fn distance(&self, other: &Genome) -> f32 {
+        1.0 - self.similarity(other)
+    }
+
+    fn gene_bits(&self) -> Vec<u32> {
+        self.genes.iter().map(Gene::bits).collect()
+    }
+
     pub fn get_color(&self) -> HSLColor {
         let colors: Vec<HSLColor> = self.genes.iter().map(|gene| gene.get_color()).collect();
         let mut total_weight = 0.0;
@@ -92,34 +184,162 @@ impl Genome {
     }
 }
 
+fn bit_similarity(a: &[u32], b: &[u32]) -> f32 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let matching: u32 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| 32 - (a ^ b).count_ones())
+        .sum();
+    matching as f32 / (32 * longest) as f32
+}
+
+//populations with more pairs than this get their diversity estimated from a sample of pairs
+pub const DIVERSITY_PAIRS: usize = 4096;
+
+//mean distance between two genomes of the population, 0 once every genome is the same
+pub fn diversity(genomes: &[&Genome]) -> f32 {
+    let bits: Vec<Vec<u32>> = genomes.iter().map(|genome| genome.gene_bits()).collect();
+    let n = bits.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let distance = |(a, b): (usize, usize)| 1.0 - bit_similarity(&bits[a], &bits[b]);
+
+    let pairs = n * (n - 1) / 2;
+    if pairs <= DIVERSITY_PAIRS {
+        let total: f32 = (0..n)
+            .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+            .map(distance)
+            .sum();
+        return total / pairs as f32;
+    }
+
+    //a fixed seed keeps the estimate reproducible without touching the simulation rng
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let total: f32 = (0..DIVERSITY_PAIRS)
+        .map(|_| {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n - 1);
+            //never pair a genome with itself
+            (a, if b >= a { b + 1 } else { b })
+        })
+        .map(distance)
+        .sum();
+    total / DIVERSITY_PAIRS as f32
+}
+
 impl From<NeuralNet> for Genome {
     fn from(net: NeuralNet) -> Self {
         let mut genes: Vec<Gene> = vec![];
-        net.for_each_neuron(|_, _, neuron| {
+        net.for_each_neuron(|layer_index, neuron_index, neuron| {
+            if let Some(activation) = neuron.activation {
+                genes.push(
+                    ConnectionPacked::from_activation((layer_index, neuron_index), activation)
+                        .into(),
+                );
+            }
+            if neuron.bias != 0.0 {
+                genes.push(
+                    ConnectionPacked::from_bias((layer_index, neuron_index), neuron.bias).into(),
+                );
+            }
             for connection in &neuron.connections {
                 let packed: ConnectionPacked = connection.clone().into();
-                let hex = packed.to_hex();
-                genes.push(Gene(hex));
+                genes.push(packed.into());
             }
         });
 
         let traits = net
             .get_summary_connections()
             .into_iter()
-            .map(|connection| {
-                let packed: ConnectionPacked = connection.into();
-                let hex = packed.to_hex();
-                Gene(hex)
-            })
+            .map(|connection| connection.into())
             .collect();
-        Genome { genes, traits }
+        Genome {
+            layers: net.definition(),
+            genes,
+            traits,
+        }
+    }
+}
+
+impl TryFrom<&Genome> for NeuralNet {
+    type Error = GenomeError;
+
+    fn try_from(genome: &Genome) -> Result<Self, Self::Error> {
+        let valid_definition = (2..=MAX_PACKED_INDEX).contains(&genome.layers.len())
+            && genome
+                .layers
+                .iter()
+                .all(|size| (1..=MAX_PACKED_INDEX).contains(size));
+        if !valid_definition {
+            return Err(GenomeError::InvalidDefinition(genome.layers.clone()));
+        }
+
+        let mut net = NeuralNet::new(genome.layers.clone());
+        for gene in &genome.genes {
+            let packed = ConnectionPacked::try_from(gene)?;
+            //input neurons have neither an activation nor a bias
+            let past_input = |(layer, neuron): (usize, usize)| {
+                (1..net.layers.len()).contains(&layer) && neuron < net.layers[layer].neurons.len()
+            };
+            if let Some((neuron, activation)) = packed.get_activation() {
+                if !past_input(neuron) {
+                    return Err(GenomeError::InvalidConnection(gene.0.clone()));
+                }
+                net.set_neuron_activation(neuron, activation);
+                continue;
+            }
+            if let Some((neuron, bias)) = packed.get_bias() {
+                if !past_input(neuron) {
+                    return Err(GenomeError::InvalidConnection(gene.0.clone()));
+                }
+                //duplicated bias genes add up like duplicated connections do
+                net.set_bias(neuron, net.layers[neuron.0].neurons[neuron.1].bias + bias);
+                continue;
+            }
+
+            let connection: Connection = packed.into();
+            if !net.is_valid_connection(connection.from, connection.to) {
+                return Err(GenomeError::InvalidConnection(gene.0.clone()));
+            }
+            net.add_connection(connection.from, connection.to, connection.weight);
+        }
+
+        Ok(net)
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Gene(String);
 
+impl From<ConnectionPacked> for Gene {
+    fn from(packed: ConnectionPacked) -> Self {
+        Gene(packed.to_hex())
+    }
+}
+
+impl TryFrom<&Gene> for ConnectionPacked {
+    type Error = GenomeError;
+
+    fn try_from(gene: &Gene) -> Result<Self, Self::Error> {
+        ConnectionPacked::from_hex(&gene.0)
+    }
+}
+
 impl Gene {
+    //the gene as a packed 32 bit value (from, to, weight low byte, weight high byte)
+    pub fn bits(&self) -> u32 {
+        u32::from_str_radix(&self.0, 16).expect("Failed to parse gene hex string")
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        Gene(format!("{:08x}", bits))
+    }
+
     pub fn get_weight(&self) -> f32 {
         let gene_string = &self.0;
         let bytes = hex::decode(gene_string).unwrap();
@@ -141,7 +361,7 @@ impl Gene {
 
             let color = HSLColor {
                 hue: color_angle,
-                saturation: color_weight.abs() / WEIGHT_RANGE,
+                saturation: (color_weight.abs() / WEIGHT_RANGE).min(1.0),
             };
 
             // println!("gene - {} {:?}", color.saturation, color.to_hex());
@@ -156,11 +376,12 @@ impl Gene {
 mod test {
 
     use super::*;
+    use crate::neural::Activation;
 
     #[test]
     fn convert_random_net_to_genome() {
         let mut test_net = NeuralNet::new(vec![2, 3, 2]);
-        test_net.init_random_connections(3, (-4.0, 4.0));
+        test_net.init_random_connections(3, (-4.0, 4.0), &mut rand::thread_rng());
         let genome: Genome = test_net.into();
         println!("{:?}", genome);
     }
@@ -187,13 +408,173 @@ mod test {
         println!("{:#?}", color.to_hex());
     }
 
+    #[test]
+    fn decode_genome_round_trip() {
+        for _ in 0..20 {
+            let mut test_net = NeuralNet::new(vec![4, 3, 3, 2]);
+            test_net.init_random_connections(
+                8,
+                (-WEIGHT_RANGE, WEIGHT_RANGE),
+                &mut rand::thread_rng(),
+            );
+            let genome: Genome = test_net.clone().into();
+
+            let decoded = NeuralNet::try_from(&genome).unwrap();
+            assert!(decoded.definition() == test_net.definition());
+
+            let mut original_connections = vec![];
+            test_net.for_each_neuron(|_, _, neuron| {
+                original_connections.extend(neuron.connections.clone())
+            });
+            let mut decoded_connections = vec![];
+            decoded.for_each_neuron(|_, _, neuron| {
+                decoded_connections.extend(neuron.connections.clone())
+            });
+            assert!(original_connections.len() == decoded_connections.len());
+            for (original, decoded) in original_connections.iter().zip(&decoded_connections) {
+                assert!(original.from == decoded.from);
+                assert!(original.to == decoded.to);
+                assert!((original.weight - decoded.weight).abs() <= 0.001);
+            }
+
+            //decoding is lossless once the weights have been quantized
+            let reencoded: Genome = decoded.clone().into();
+            assert!(reencoded == genome);
+            let redecoded = NeuralNet::try_from(&reencoded).unwrap();
+            assert!(
+                redecoded.forward(vec![0.1, 0.5, 0.9, 0.3])
+                    == decoded.forward(vec![0.1, 0.5, 0.9, 0.3])
+            );
+        }
+    }
+
+    #[test]
+    fn decode_activation_genes() {
+        let mut test_net = NeuralNet::new(vec![2, 3, 2]);
+        test_net.add_connection((0, 0), (1, 1), 1.2);
+        test_net.set_neuron_activation((1, 1), Activation::Gaussian);
+        test_net.set_neuron_activation((2, 0), Activation::Step);
+        let genome: Genome = test_net.into();
+        assert!(genome.length() == 3);
+        assert!(genome.genes()[1] == Gene("11070000".to_string()));
+
+        let decoded = NeuralNet::try_from(&genome).unwrap();
+        assert!(decoded.activation((1, 1)) == Activation::Gaussian);
+        assert!(decoded.activation((1, 0)) == Activation::Tanh);
+        assert!(decoded.activation((2, 0)) == Activation::Step);
+        assert!(Genome::from(decoded) == genome);
+    }
+
+    #[test]
+    fn decode_bias_genes() {
+        let mut test_net = NeuralNet::new(vec![2, 2]);
+        test_net.add_connection((0, 1), (1, 0), 1.0);
+        test_net.set_bias((1, 0), -1.5);
+        let genome: Genome = test_net.into();
+        assert!(genome.length() == 2);
+        assert!(genome.genes()[1] == Gene("100824fa".to_string()));
+
+        let decoded = NeuralNet::try_from(&genome).unwrap();
+        assert!(decoded.layers[1].neurons[0].bias == -1.5);
+        assert!(decoded.layers[1].neurons[1].bias == 0.0);
+        assert!(Genome::from(decoded) == genome);
+
+        //duplicated bias genes add up
+        let genome = Genome::from_genes(vec![2, 2], vec![genome.genes()[1].clone(); 2]);
+        let decoded = NeuralNet::try_from(&genome).unwrap();
+        assert!(decoded.layers[1].neurons[0].bias == -3.0);
+    }
+
+    #[test]
+    fn genome_from_genes() {
+        let mut test_net = NeuralNet::new(vec![3, 2, 3]);
+        test_net.init_random_connections(
+            10,
+            (-WEIGHT_RANGE, WEIGHT_RANGE),
+            &mut rand::thread_rng(),
+        );
+        let genome: Genome = test_net.into();
+        let rebuilt = Genome::from_genes(genome.layers.clone(), genome.genes.clone());
+        assert!(rebuilt == genome);
+
+        for gene in genome.genes() {
+            assert!(Gene::from_bits(gene.bits()) == *gene);
+        }
+    }
+
+    #[test]
+    fn decode_invalid_genomes() {
+        let genome = Genome::new(vec![2, 2], vec![Gene("zz".to_string())], vec![]);
+        assert!(
+            NeuralNet::try_from(&genome).err() == Some(GenomeError::InvalidHex("zz".to_string()))
+        );
+
+        let genome = Genome::new(vec![2, 2], vec![Gene("0010".to_string())], vec![]);
+        assert!(
+            NeuralNet::try_from(&genome).err()
+                == Some(GenomeError::InvalidLength("0010".to_string()))
+        );
+
+        //neuron (1, 5) does not exist
+        let genome = Genome::new(vec![2, 2], vec![Gene("0015e803".to_string())], vec![]);
+        assert!(
+            NeuralNet::try_from(&genome).err()
+                == Some(GenomeError::InvalidConnection("0015e803".to_string()))
+        );
+
+        //bias of an input neuron
+        let genome = Genome::new(vec![2, 2], vec![Gene("0008e803".to_string())], vec![]);
+        assert!(
+            NeuralNet::try_from(&genome).err()
+                == Some(GenomeError::InvalidConnection("0008e803".to_string()))
+        );
+
+        //activation of an input neuron
+        let genome = Genome::new(vec![2, 2], vec![Gene("01020000".to_string())], vec![]);
+        assert!(
+            NeuralNet::try_from(&genome).err()
+                == Some(GenomeError::InvalidConnection("01020000".to_string()))
+        );
+
+        let genome = Genome::new(vec![2, 17], vec![], vec![]);
+        assert!(
+            NeuralNet::try_from(&genome).err() == Some(GenomeError::InvalidDefinition(vec![2, 17]))
+        );
+    }
+
     #[test]
     fn color_random_organisms() {
         for i in 0..10 {
             let mut test_net = NeuralNet::new(vec![10, 1, 1, 10]);
-            test_net.init_random_connections(10, (-4.0, 4.0));
+            test_net.init_random_connections(10, (-4.0, 4.0), &mut rand::thread_rng());
             let genome: Genome = test_net.into();
             println!("genome - {:?}\n", genome.get_color().to_hex());
         }
     }
+
+    #[test]
+    fn genome_distance() {
+        let genes =
+            |bits: &[u32]| -> Vec<Gene> { bits.iter().map(|b| Gene::from_bits(*b)).collect() };
+        let a = Genome::from_genes(vec![2, 2], genes(&[0x0000_0000, 0xffff_ffff]));
+        let b = Genome::from_genes(vec![2, 2], genes(&[0x0000_00ff, 0xffff_ffff]));
+        let c = Genome::from_genes(vec![2, 2], genes(&[0xffff_ffff]));
+
+        assert!(a.distance(&a) == 0.0);
+        assert!(a.distance(&b) == 8.0 / 64.0);
+        assert!(a.distance(&b) == b.distance(&a));
+        //the missing second gene counts as completely different
+        assert!(a.distance(&c) == 1.0);
+
+        assert!(diversity(&[]) == 0.0);
+        assert!(diversity(&[&a, &a, &a]) == 0.0);
+        assert!(diversity(&[&a, &b]) == 8.0 / 64.0);
+        assert!(diversity(&[&a, &b, &c]) == (8.0 / 64.0 + 1.0 + 1.0 - 8.0 / 64.0) / 3.0);
+
+        //large populations are sampled, the estimate stays close and is reproducible
+        let population: Vec<&Genome> = (0..200).map(|i| if i % 2 == 0 { &a } else { &c }).collect();
+        let exact = (100.0 * 100.0) / (200.0 * 199.0 / 2.0);
+        assert!((diversity(&population) - exact).abs() < 0.05);
+        assert!(diversity(&population) == diversity(&population));
+    }
 }
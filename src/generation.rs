@@ -0,0 +1,197 @@
+use crate::{
+    config::{BrainConfig, EnergyConfig},
+    crossover::{crossover, CrossoverMode},
+    environment::Environment,
+    gene::Genome,
+    mutation::{mutate, MutationRates},
+    organism::{Direction, Organism},
+    selection::SelectionCriterion,
+    utils::{vec2_to_i32, Grid2d},
+};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Reproduction {
+    //offspring inherit the genome of a single survivor
+    Asexual,
+    //offspring combine the genomes of a survivor and the nearest surviving neighbour within
+    //mate_radius cells, survivors without a neighbour reproduce asexually
+    Sexual {
+        crossover: CrossoverMode,
+        mate_radius: usize,
+    },
+}
+
+//number of generations to run before exiting in headless mode
+#[derive(Resource)]
+pub struct GenerationLimit(pub usize);
+
+#[derive(Resource)]
+pub struct Generation {
+    pub steps_per_generation: usize,
+    pub population: usize,
+    pub criterion: Box<dyn SelectionCriterion>,
+    pub mutation_rates: MutationRates,
+    pub reproduction: Reproduction,
+    //brains of the random organisms spawned when the population goes extinct
+    pub brain: BrainConfig,
+    pub energy: EnergyConfig,
+    pub step: usize,
+    pub count: usize,
+}
+
+impl Generation {
+    pub fn new(
+        steps_per_generation: usize,
+        population: usize,
+        criterion: Box<dyn SelectionCriterion>,
+        mutation_rates: MutationRates,
+        reproduction: Reproduction,
+        brain: BrainConfig,
+        energy: EnergyConfig,
+    ) -> Self {
+        Generation {
+            steps_per_generation,
+            population,
+            criterion,
+            mutation_rates,
+            reproduction,
+            brain,
+            energy,
+            step: 0,
+            count: 0,
+        }
+    }
+
+    //advance the step counter, returns true once the generation is over
+    pub fn tick(&mut self) -> bool {
+        self.step += 1;
+        self.step >= self.steps_per_generation
+    }
+}
+
+//nearest surviving neighbour of the parent, as an index into the survivors
+fn find_mate(
+    environment: &Environment,
+    survivor_indices: &HashMap<u64, usize>,
+    parent: &Organism,
+    radius: usize,
+) -> Option<usize> {
+    let position = (parent.position.x as usize, parent.position.y as usize);
+    environment
+        .neighbours(position, radius)
+        .into_iter()
+        .filter_map(|(cell, id)| survivor_indices.get(&id).map(|index| (cell, *index)))
+        .min_by_key(|(cell, _)| {
+            let (dx, dy) = environment.offset(position, *cell);
+            dx * dx + dy * dy
+        })
+        .map(|(_, index)| index)
+}
+
+//ends the current generation: every organism is despawned and a new population is
+//spawned from the mutated genomes of the organisms that met the selection criterion
+pub fn next_generation(
+    environment: &mut Environment,
+    commands: &mut Commands,
+    organisms: Vec<(Organism, Entity)>,
+    generation: &mut Generation,
+    rng: &mut impl Rng,
+) -> usize {
+    let total = organisms.len();
+
+    let survivors: Vec<(u64, Organism)> = organisms
+        .iter()
+        .filter(|(organism, _)| {
+            let (x, y) = vec2_to_i32(organism.position);
+            generation
+                .criterion
+                .survives(organism, (x as usize, y as usize), environment)
+        })
+        .map(|(organism, entity)| (entity.to_bits(), organism.clone()))
+        .collect();
+    let survivor_indices: HashMap<u64, usize> = survivors
+        .iter()
+        .enumerate()
+        .map(|(index, (id, _))| (*id, index))
+        .collect();
+
+    //offspring genomes are picked before the grid is cleared so mates can be found
+    let mut offspring_genomes: Vec<Genome> = Vec::new();
+    if !survivors.is_empty() {
+        for _ in 0..generation.population {
+            let (_, parent) = &survivors[rng.gen_range(0..survivors.len())];
+            let genome = match generation.reproduction {
+                Reproduction::Asexual => parent.genome.clone(),
+                Reproduction::Sexual {
+                    crossover: mode,
+                    mate_radius,
+                } => match find_mate(environment, &survivor_indices, parent, mate_radius) {
+                    Some(mate) => crossover(&parent.genome, &survivors[mate].1.genome, mode, rng)
+                        .unwrap_or_else(|error| {
+                            warn!("falling back to asexual reproduction: {}", error);
+                            parent.genome.clone()
+                        }),
+                    None => parent.genome.clone(),
+                },
+            };
+            offspring_genomes.push(mutate(
+                &genome,
+                &generation.mutation_rates,
+                generation.brain.gene_kinds(),
+                rng,
+            ));
+        }
+    }
+
+    for (_, entity) in organisms {
+        commands.entity(entity).despawn();
+    }
+
+    environment.organisms = Grid2d::new(environment.organisms.width, environment.organisms.height);
+    environment.pheromones = Grid2d::new(environment.organisms.width, environment.organisms.height);
+
+    if survivors.is_empty() {
+        // population went extinct, start over from random organisms
+        environment.spawn_n_random_organisms(
+            commands,
+            generation.population,
+            &generation.brain,
+            generation.energy.initial,
+            rng,
+        );
+    } else {
+        for genome in offspring_genomes {
+            let Some(position) = environment.random_empty_cell(rng) else {
+                break;
+            };
+            match Organism::from_genome(genome, Vec2::new(position.0 as f32, position.1 as f32)) {
+                Ok(mut offspring) => {
+                    offspring
+                        .brain
+                        .set_layer_activations(&generation.brain.activations);
+                    offspring.heading = Direction::random(rng);
+                    offspring.energy = generation.energy.initial;
+                    environment.spawn_organism(commands, offspring, position)
+                }
+                Err(error) => warn!("discarding offspring with invalid genome: {}", error),
+            }
+        }
+    }
+
+    info!(
+        "generation {} - {}/{} survived",
+        generation.count,
+        survivors.len(),
+        total
+    );
+
+    generation.count += 1;
+    generation.step = 0;
+
+    survivors.len()
+}
@@ -2,7 +2,14 @@ use crate::{
     neural::{self, NeuralNet, Neuron},
     utils::f32_to_vec2,
 };
-use bevy::{color::palettes::css::BLACK, prelude::*};
+use bevy::{
+    color::palettes::{
+        css::BLACK,
+        tailwind::{GRAY_700, GREEN_600, RED_600},
+    },
+    prelude::*,
+    sprite::Anchor,
+};
 use bevy_prototype_lyon::prelude::*;
 use std::collections::HashMap;
 
@@ -24,10 +31,20 @@ pub struct DiagramConfig {
 
 #[derive(Debug)]
 pub struct GraphDiagram {
-    nodes: Vec<(f32, f32)>,
+    //every node that isn't a dummy
+    nodes: Vec<DiagramNode>,
     edges: Vec<DiagramEdge>,
     config: DiagramConfig,
+    labels: HashMap<(usize, usize), String>,
+}
+#[derive(Debug)]
+pub struct DiagramNode {
+    //(layer, vertex)
+    pub index: (usize, usize),
+    pub position: (f32, f32),
+    pub bias: f32,
 }
+
 #[derive(Debug)]
 pub enum DiagramEdge {
     Straight(StraightEdge),
@@ -38,6 +55,7 @@ pub enum DiagramEdge {
 pub struct StraightEdge {
     pub start: (f32, f32),
     pub end: (f32, f32),
+    pub weight: f32,
 }
 
 #[derive(Debug)]
@@ -46,7 +64,26 @@ pub struct BezierEdge {
 }
 
 impl GraphDiagram {
-    pub fn spawn(self, commands: &mut Commands) {
+    //text drawn next to the nodes, keyed by (layer, vertex)
+    pub fn with_labels(mut self, labels: HashMap<(usize, usize), String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    //spawns the diagram, returns the entity all its shapes are children of. Edges are green
+    //for positive and red for negative weights, thicker the stronger they are. Nodes with a
+    //bias are outlined the same way
+    pub fn spawn(self, commands: &mut Commands) -> Entity {
+        let strongest = self
+            .edges
+            .iter()
+            .filter_map(|edge| match edge {
+                DiagramEdge::Straight(straight_edge) => Some(straight_edge.weight.abs()),
+                DiagramEdge::Bezier(_) => None,
+            })
+            .chain(self.nodes.iter().map(|node| node.bias.abs()))
+            .fold(0.0, f32::max);
+
         commands
             .spawn(SpatialBundle {
                 transform: Transform::from_translation(Vec3::new(
@@ -68,7 +105,14 @@ impl GraphDiagram {
                                     )),
                                     ..default()
                                 },
-                                Stroke::new(Color::BLACK, self.config.arrow_thickness),
+                                Stroke::new(
+                                    edge_color(straight_edge.weight),
+                                    edge_thickness(
+                                        straight_edge.weight,
+                                        strongest,
+                                        self.config.arrow_thickness,
+                                    ),
+                                ),
                             ));
                         }
 
@@ -101,23 +145,70 @@ impl GraphDiagram {
                         }
                     }
                 }
-                for (x, y) in self.nodes {
-                    parent.spawn(ShapeBundle {
-                        path: GeometryBuilder::build_as(&shapes::Circle {
-                            radius: self.config.node_radius,
-                            ..shapes::Circle::default()
-                        }),
-                        spatial: SpatialBundle {
-                            transform: Transform::from_translation(Vec3::new(x, y, 0.0)),
-                            ..Default::default()
+                for node in self.nodes {
+                    let (x, y) = node.position;
+                    let mut spawned = parent.spawn((
+                        ShapeBundle {
+                            path: GeometryBuilder::build_as(&shapes::Circle {
+                                radius: self.config.node_radius,
+                                ..shapes::Circle::default()
+                            }),
+                            spatial: SpatialBundle {
+                                transform: Transform::from_translation(Vec3::new(x, y, 0.1)),
+                                ..Default::default()
+                            },
+                            ..default()
                         },
-                        ..default()
-                    });
+                        Fill::color(Color::Srgba(GRAY_700)),
+                    ));
+                    if node.bias != 0.0 {
+                        spawned.insert(Stroke::new(
+                            edge_color(node.bias),
+                            edge_thickness(node.bias, strongest, self.config.arrow_thickness),
+                        ));
+                    }
+
+                    if let Some(label) = self.labels.get(&node.index) {
+                        parent.spawn(Text2dBundle {
+                            text: Text::from_section(
+                                label.clone(),
+                                TextStyle {
+                                    font_size: 12.0,
+                                    color: Color::Srgba(BLACK),
+                                    ..default()
+                                },
+                            ),
+                            text_anchor: Anchor::CenterLeft,
+                            transform: Transform::from_translation(Vec3::new(
+                                x + self.config.node_radius + 2.0,
+                                y,
+                                0.2,
+                            )),
+                            ..default()
+                        });
+                    }
                 }
-            });
+            })
+            .id()
     }
 }
 
+fn edge_color(weight: f32) -> Color {
+    if weight < 0.0 {
+        Color::Srgba(RED_600)
+    } else {
+        Color::Srgba(GREEN_600)
+    }
+}
+
+//from a quarter of the full thickness for the weakest to twice it for the strongest edge
+fn edge_thickness(weight: f32, strongest: f32, thickness: f32) -> f32 {
+    if strongest <= 0.0 {
+        return thickness;
+    }
+    thickness * (0.25 + 1.75 * weight.abs() / strongest)
+}
+
 #[derive(Clone, Debug)]
 pub struct Layer {
     pub vertices: Vec<Vertex>,
@@ -129,6 +220,7 @@ impl Layer {
         let vertices = (0..length)
             .map(|_| Vertex {
                 edges: Vec::new(),
+                bias: 0.0,
                 is_dummy: false,
             })
             .collect();
@@ -321,6 +413,7 @@ impl Graph {
                 edges.push(DiagramEdge::Straight(StraightEdge {
                     start: (*x, *y),
                     end: (*to_x, *to_y),
+                    weight: edge.weight,
                 }));
 
                 if from_vertex.is_dummy {
@@ -340,10 +433,22 @@ impl Graph {
             nodes_with_positions.remove(&node);
         }
 
+        let mut nodes: Vec<DiagramNode> = nodes_with_positions
+            .into_iter()
+            .map(|(index, position)| DiagramNode {
+                index,
+                position,
+                bias: self
+                    .get_vertex(index)
+                    .map_or(0.0, |(_, vertex)| vertex.bias),
+            })
+            .collect();
+        nodes.sort_by_key(|node| node.index);
         GraphDiagram {
-            nodes: nodes_with_positions.values().cloned().collect(),
+            nodes,
             edges,
             config,
+            labels: HashMap::new(),
         }
     }
 }
@@ -372,7 +477,10 @@ impl From<NeuralNet> for Graph {
                         neuron
                     })
                     .collect();
-                neural::Layer { neurons }
+                neural::Layer {
+                    neurons,
+                    activation: layer.activation,
+                }
             })
             .collect();
 
@@ -387,15 +495,20 @@ impl From<NeuralNet> for Graph {
                             to: (edge.to.0, edge.to.1),
                             weight: edge.weight,
                         }],
+                        activation: None,
+                        bias: 0.0,
                     });
                 } else {
                     let next_vertex_index = layers[layer + 1].neurons.len();
+                    //every segment of a long edge carries its weight so it's drawn the same
                     layers[layer].neurons.push(Neuron {
                         connections: vec![neural::Connection {
                             from: (from_layer_index, from_neuron_index),
                             to: (layer + 1, next_vertex_index),
-                            weight: 0.0,
+                            weight: edge.weight,
                         }],
+                        activation: None,
+                        bias: 0.0,
                     });
                 }
                 let new_neuron_index = layers[layer].neurons.len() - 1;
@@ -431,6 +544,7 @@ impl From<NeuralNet> for Graph {
                                     to: edge.to,
                                 })
                                 .collect(),
+                            bias: neuron.bias,
                             is_dummy: false,
                         })
                         .collect(),
@@ -458,6 +572,7 @@ pub struct Edge {
 pub struct Vertex {
     //first index is the layer and the second index is the connected vertex index
     edges: Vec<Edge>,
+    bias: f32,
     is_dummy: bool,
 }
 
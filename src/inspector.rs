@@ -0,0 +1,222 @@
+use crate::{
+    config::Config,
+    environment::Environment,
+    generation::Generation,
+    graph::{DiagramConfig, Graph},
+    organism::Organism,
+};
+use bevy::{
+    color::palettes::css::{BLACK, ORANGE_RED},
+    prelude::*,
+    sprite::Anchor,
+    window::PrimaryWindow,
+};
+use bevy_prototype_lyon::prelude::*;
+use std::collections::HashMap;
+
+//genes per line of the genome listing
+const GENES_PER_LINE: usize = 5;
+
+//the organism picked with a left click, shown in a panel right of the environment
+#[derive(Resource, Default)]
+pub struct Inspector {
+    pub selected: Option<Entity>,
+    //the organism the panel was built for and the panel itself
+    shown: Option<(Entity, Entity)>,
+}
+
+//the text of the panel that changes every step
+#[derive(Component)]
+pub struct InspectorText;
+
+//ring around the selected organism
+#[derive(Component)]
+pub struct SelectionMarker;
+
+//left click selects the organism under the cursor, clicking an empty cell clears the selection
+pub fn select_organism(
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    env: Res<Environment>,
+    mut inspector: ResMut<Inspector>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single())
+    else {
+        return;
+    };
+    let Some(world) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+    else {
+        return;
+    };
+
+    //clicks outside the environment (e.g. on the panel) keep the selection
+    if let Some((x, y)) = env.world_to_cell(world) {
+        inspector.selected = env.organisms.get(x, y).map(|id| Entity::from_bits(*id));
+    }
+}
+
+//keeps the panel in sync with the selected organism, it disappears when the organism dies
+pub fn update_inspector(
+    mut commands: Commands,
+    mut inspector: ResMut<Inspector>,
+    env: Res<Environment>,
+    generation: Res<Generation>,
+    config: Res<Config>,
+    organisms: Query<&Organism>,
+    mut texts: Query<&mut Text, With<InspectorText>>,
+    mut markers: Query<&mut Transform, With<SelectionMarker>>,
+) {
+    let selected = inspector.selected.and_then(|entity| {
+        organisms
+            .get(entity)
+            .ok()
+            .map(|organism| (entity, organism))
+    });
+    if selected.is_none() && inspector.selected.is_some() {
+        inspector.selected = None;
+    }
+
+    let shown = inspector.shown.map(|(organism, _)| organism);
+    if selected.map(|(entity, _)| entity) != shown {
+        if let Some((_, panel)) = inspector.shown.take() {
+            commands.entity(panel).despawn_recursive();
+        }
+        if let Some((entity, organism)) = selected {
+            let panel = spawn_panel(&mut commands, &env, &generation, &config, organism);
+            inspector.shown = Some((entity, panel));
+        }
+        return;
+    }
+
+    if let Some((_, organism)) = selected {
+        let description = describe(organism, &generation, &config);
+        for mut text in texts.iter_mut() {
+            text.sections[0].value.clone_from(&description);
+        }
+        let position = env.cell_to_world(organism.position);
+        for mut marker in markers.iter_mut() {
+            marker.translation = position.extend(2.0);
+        }
+    }
+}
+
+fn describe(organism: &Organism, generation: &Generation, config: &Config) -> String {
+    let color = organism.genome.get_color();
+    let mut description = format!(
+        "position ({}, {}) heading {:?}\nage {} steps",
+        organism.position.x, organism.position.y, organism.heading, generation.step
+    );
+    if config.energy.enabled {
+        description += &format!("  energy {:.1}", organism.energy);
+    }
+    description += &format!(
+        "\ncolour {} (hue {}, saturation {:.2})",
+        color.to_hex(),
+        color.hue,
+        color.saturation
+    );
+    if let Some(species) = organism.species {
+        description += &format!("\nspecies {}", species);
+    }
+
+    description += &format!(
+        "\ngenome {:?}, {} genes",
+        organism.genome.layers(),
+        organism.genome.length()
+    );
+    for line in organism.genome.genes().chunks(GENES_PER_LINE) {
+        let genes: Vec<String> = line
+            .iter()
+            .map(|gene| format!("{:08x}", gene.bits()))
+            .collect();
+        description += &format!("\n{}", genes.join(" "));
+    }
+    description
+}
+
+//brain diagram labelled with the sensor and action codes, genome and state of the organism
+fn spawn_panel(
+    commands: &mut Commands,
+    env: &Environment,
+    generation: &Generation,
+    config: &Config,
+    organism: &Organism,
+) -> Entity {
+    let left = env.x + env.width / 2.0 + 30.0;
+    let top = env.y + env.height / 2.0;
+    let bottom = env.y - env.height / 2.0;
+
+    let text = commands
+        .spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    describe(organism, generation, config),
+                    TextStyle {
+                        font_size: 13.0,
+                        color: Color::Srgba(BLACK),
+                        ..default()
+                    },
+                ),
+                text_anchor: Anchor::TopLeft,
+                transform: Transform::from_translation(Vec3::new(left, top, 2.0)),
+                ..default()
+            },
+            InspectorText,
+        ))
+        .id();
+
+    let marker = commands
+        .spawn((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shapes::Circle {
+                    radius: env.organism_size,
+                    ..shapes::Circle::default()
+                }),
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(
+                        env.cell_to_world(organism.position).extend(2.0),
+                    ),
+                    ..default()
+                },
+                ..default()
+            },
+            Stroke::new(Color::Srgba(ORANGE_RED), 1.5),
+            SelectionMarker,
+        ))
+        .id();
+
+    //inputs along the bottom, outputs along the top
+    let layers = organism.brain.layers.len();
+    let mut labels = HashMap::new();
+    for (index, sensor) in config.brain.sensors.iter().enumerate() {
+        labels.insert((0, index), sensor.code().to_string());
+    }
+    for (index, action) in config.brain.actions.iter().enumerate() {
+        labels.insert((layers - 1, index), action.code().to_string());
+    }
+    let mut graph = Graph::from(organism.brain.clone());
+    graph.sort_edges();
+    let diagram = graph
+        .get_diagram(DiagramConfig {
+            position: (left, bottom + 10.0),
+            padding: 10.0,
+            node_radius: 6.0,
+            same_rank_scale: 0.5,
+            width: 300.0,
+            height: 200.0,
+            arrow_thickness: 2.0,
+        })
+        .with_labels(labels)
+        .spawn(commands);
+
+    commands
+        .spawn(SpatialBundle::default())
+        .push_children(&[text, marker, diagram])
+        .id()
+}
@@ -1,74 +1,265 @@
+mod actions;
+mod cli;
+mod config;
+mod controls;
+mod crossover;
 mod environment;
 mod gene;
+mod generation;
 mod graph;
+mod inspector;
+mod mutation;
 mod neural;
+mod obstacles;
 mod organism;
+mod selection;
+mod sensors;
+mod snapshot;
+mod species;
+mod stats;
 mod systems;
 mod utils;
 
 use bevy::{
     color::palettes::{
-        css::{BLACK, GHOST_WHITE, WHITE},
+        css::{BLACK, DARK_SLATE_GRAY, GHOST_WHITE, GREEN, WHITE},
         tailwind::{GRAY_100, GRAY_200},
     },
+    log::LogPlugin,
     prelude::*,
 };
 use bevy_prototype_lyon::prelude::*;
-use environment::{Environment, SimulationSpeed};
-use graph::{DiagramConfig, Graph, GraphDiagram};
-use neural::{NeuralNet, WEIGHT_RANGE};
-use organism::Organism;
-use systems::environment_step;
+use cli::Args;
+use config::Config;
+use controls::{
+    control_buttons, control_keys, run_simulation, spawn_overlay, step_simulation,
+    tick_simulation_timer, update_overlay, Simulation, SimulationSpeed, SimulationStep,
+};
+use environment::Environment;
+use generation::{Generation, GenerationLimit};
+use inspector::{select_organism, update_inspector, Inspector};
+use neural::BrainState;
+use obstacles::obstacle_runs;
+use organism::{Direction, Organism};
+use selection::Zone;
+use snapshot::{
+    restore_pending_snapshot, save_snapshot_on_exit, snapshot_keys, PendingSnapshot, Snapshot,
+    SnapshotPath,
+};
+use species::{assign_species, color_species, SpeciesTracker};
+use stats::Statistics;
+use std::path::PathBuf;
+use systems::{draw_food, environment_step, exit_after_generations, FoodShapes};
+use utils::SimRng;
 
-const ORGANISM_SIZE: f32 = 5.0;
+const DEFAULT_SNAPSHOT_PATH: &str = "snapshot.evo";
 
-fn setup(mut env: ResMut<Environment>, mut commands: Commands) {
+fn draw_environment(env: Res<Environment>, generation: Res<Generation>, mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
-    //draw boundary
-    commands.spawn((
-        ShapeBundle {
-            path: GeometryBuilder::build_as(&shapes::Rectangle {
-                extents: Vec2::new(env.width + env.thickness, env.height + env.thickness),
-                ..shapes::Rectangle::default()
-            }),
-            spatial: SpatialBundle {
-                transform: Transform::from_translation(Vec3::new(env.x, env.y, 0.0)),
+    //draw boundary, wrapped edges are drawn faintly since organisms pass through them
+    let (half_width, half_height) = (
+        (env.width + env.thickness) / 2.0,
+        (env.height + env.thickness) / 2.0,
+    );
+    let corners = [
+        Vec2::new(-half_width, -half_height),
+        Vec2::new(-half_width, half_height),
+        Vec2::new(half_width, half_height),
+        Vec2::new(half_width, -half_height),
+    ];
+    //west, north, east and south edges
+    for (side, (start, end)) in corners
+        .iter()
+        .zip(corners.iter().cycle().skip(1))
+        .enumerate()
+    {
+        let wraps = match side {
+            0 | 2 => env.topology.wraps_x(),
+            _ => env.topology.wraps_y(),
+        };
+        let color = if wraps {
+            Color::Srgba(GRAY_200)
+        } else {
+            env.color
+        };
+        commands.spawn((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shapes::Line(*start, *end)),
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(Vec3::new(env.x, env.y, 0.0)),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        Stroke::new(env.color, env.thickness),
-    ));
+            Stroke::new(color, env.thickness),
+        ));
+    }
+
+    //draw obstacles, one rectangle per horizontal run of wall cells
+    for (y, first, last) in obstacle_runs(&env.obstacles) {
+        let (min, max) = (
+            Vec2::new(first as f32, y as f32),
+            Vec2::new(last as f32, y as f32),
+        );
+        commands.spawn((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shapes::Rectangle {
+                    extents: (max - min + Vec2::ONE) * env.organism_size,
+                    ..shapes::Rectangle::default()
+                }),
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(
+                        env.cell_to_world((min + max) / 2.0).extend(0.6),
+                    ),
+                    ..default()
+                },
+                ..default()
+            },
+            Fill::color(Color::Srgba(DARK_SLATE_GRAY)),
+        ));
+    }
+
+    //draw safe zones
+    for zone in generation.criterion.zones(&env) {
+        let (shape, center) = match zone {
+            Zone::Rectangle { min, max } => (
+                GeometryBuilder::build_as(&shapes::Rectangle {
+                    extents: (max - min + Vec2::ONE) * env.organism_size,
+                    ..shapes::Rectangle::default()
+                }),
+                env.cell_to_world((min + max) / 2.0),
+            ),
+            Zone::Circle { center, radius } => (
+                GeometryBuilder::build_as(&shapes::Circle {
+                    radius: radius * env.organism_size,
+                    ..shapes::Circle::default()
+                }),
+                env.cell_to_world(center),
+            ),
+        };
+        commands.spawn((
+            ShapeBundle {
+                path: shape,
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(center.extend(0.5)),
+                    ..default()
+                },
+                ..default()
+            },
+            Fill::color(Color::Srgba(GREEN.with_alpha(0.2))),
+        ));
+    }
+}
 
-    for _ in 0..2 {
-        let mut neural_net_1 = NeuralNet::new(vec![2, 1, 2]);
-        neural_net_1.init_random_connections(5, (-WEIGHT_RANGE, WEIGHT_RANGE));
+fn setup(
+    config: Res<Config>,
+    mut env: ResMut<Environment>,
+    mut rng: ResMut<SimRng>,
+    mut commands: Commands,
+) {
+    info!("seed {}", rng.seed);
+    for _ in 0..config.population.founder_types {
+        let neural_net_1 = config.brain.random_brain(&mut *rng);
 
         let organism_1 = Organism {
             genome: neural_net_1.clone().into(),
             brain: neural_net_1,
             position: Vec2::new(0.0, 0.0),
+            heading: Direction::North,
+            last_move: None,
+            oscillator_period: None,
+            energy: config.energy.initial,
+            species: None,
+            memory: BrainState::default(),
         };
 
-        env.spawn_organism_n(&mut commands, organism_1, 100);
+        env.spawn_organism_n(
+            &mut commands,
+            organism_1,
+            config.population.organisms_per_founder,
+            &mut *rng,
+        );
     }
 
-    // env.spawn_n_random_organisms(&mut commands, 300);
+    if config.energy.enabled {
+        let energy = &config.energy;
+        env.grow_food(
+            energy.initial_food,
+            energy.initial_food.max(energy.max_food),
+            &mut *rng,
+        );
+    }
+
+    // env.spawn_n_random_organisms(&mut commands, 300, &mut *rng);
 }
 
-fn main() {
-    let environment = Environment::new(
-        400.0,
-        400.0,
+fn build_app(args: &Args, config: Config, snapshot: Option<Snapshot>) -> App {
+    let mut environment = Environment::new(
+        config.world.width,
+        config.world.height,
         0.0,
         0.0,
         Color::Srgba(BLACK),
         2.0,
         0,
-        ORGANISM_SIZE,
+        config.world.organism_size,
+    );
+    environment.headless = args.headless;
+    environment.pheromone_decay = config.world.pheromone_decay;
+    environment.topology = config.world.topology;
+    environment.obstacles = config
+        .obstacles
+        .grid(environment.organisms.width, environment.organisms.height);
+
+    let rng = match args.seed.or(config.simulation.seed) {
+        Some(seed) => SimRng::new(seed),
+        None => SimRng::from_entropy(),
+    };
+
+    let generation = Generation::new(
+        config.generation.steps,
+        config.population.size(),
+        config.selection.criterion(),
+        config.mutation,
+        config.generation.reproduction,
+        config.brain.clone(),
+        config.energy.clone(),
     );
-    App::new()
-        .add_plugins((
+    let step_interval = config.simulation.step_interval;
+
+    let mut app = App::new();
+    app.insert_resource(environment)
+        .insert_resource(rng)
+        .insert_resource(generation)
+        .insert_resource(config)
+        .init_resource::<SpeciesTracker>()
+        .insert_resource(SnapshotPath(
+            args.save
+                .clone()
+                .or(args.load.clone())
+                .unwrap_or(PathBuf::from(DEFAULT_SNAPSHOT_PATH)),
+        ));
+
+    app.add_systems(SimulationStep, (environment_step, assign_species).chain());
+
+    match snapshot {
+        Some(snapshot) => app
+            .insert_resource(PendingSnapshot(snapshot))
+            .add_systems(Startup, restore_pending_snapshot),
+        //sorted into species right away so they can be coloured by species from the start
+        None => app.add_systems(Startup, (setup, assign_species).chain()),
+    };
+
+    if args.headless {
+        //step every update without waiting on the simulation timer
+        app.add_plugins(MinimalPlugins)
+            .add_systems(Update, step_simulation.in_set(Simulation));
+
+        if args.save.is_some() {
+            app.add_systems(Last, save_snapshot_on_exit);
+        }
+    } else {
+        app.add_plugins((
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
                     title: String::from("evo"),
@@ -79,9 +270,297 @@ fn main() {
             ShapePlugin,
         ))
         .insert_resource(ClearColor(Color::Srgba(GRAY_100)))
-        .insert_resource(environment)
-        .insert_resource(SimulationSpeed::new(0.01))
-        .add_systems(Startup, setup)
-        .add_systems(Update, environment_step)
-        .run();
+        .insert_resource(SimulationSpeed::new(step_interval))
+        .init_resource::<FoodShapes>()
+        .init_resource::<Inspector>()
+        .add_systems(
+            Startup,
+            (
+                draw_environment.after(restore_pending_snapshot),
+                spawn_overlay,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                snapshot_keys,
+                control_keys,
+                control_buttons,
+                tick_simulation_timer,
+                run_simulation.in_set(Simulation),
+                color_species,
+                draw_food,
+                select_organism,
+                update_inspector,
+                update_overlay,
+            )
+                .chain(),
+        );
+    }
+
+    if let Some(generations) = args.generations {
+        app.insert_resource(GenerationLimit(generations))
+            .add_systems(Update, exit_after_generations.after(Simulation));
+    }
+
+    app
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    if args.gene_table {
+        println!("{}", sensors::sensor_table());
+        print!("{}", actions::action_table());
+        return;
+    }
+
+    let config = Config::load(args.config.as_deref(), &args.overrides).unwrap_or_else(|error| {
+        eprintln!("failed to load config: {}", error);
+        std::process::exit(1);
+    });
+
+    let statistics = Statistics::create(&config.stats).unwrap_or_else(|error| {
+        eprintln!("failed to create statistics file {}", error);
+        std::process::exit(1);
+    });
+
+    let snapshot = args.load.as_ref().map(|path| {
+        Snapshot::load(path, &config.brain).unwrap_or_else(|error| {
+            eprintln!("failed to load snapshot {}: {}", path.display(), error);
+            std::process::exit(1);
+        })
+    });
+
+    let mut app = build_app(&args, config, snapshot);
+    if let Some(statistics) = statistics {
+        app.insert_resource(statistics);
+    }
+    if args.headless {
+        app.add_plugins(LogPlugin::default());
+    }
+    app.run();
+}
+
+mod tests {
+    use super::*;
+
+    fn headless_app(seed: u64, snapshot: Option<Snapshot>) -> App {
+        headless_app_with(seed, Config::default(), snapshot)
+    }
+
+    fn headless_app_with(seed: u64, config: Config, snapshot: Option<Snapshot>) -> App {
+        build_app(
+            &Args {
+                headless: true,
+                seed: Some(seed),
+                ..Args::default()
+            },
+            config,
+            snapshot,
+        )
+    }
+
+    fn organisms(app: &mut App) -> Vec<(Vec2, gene::Genome)> {
+        app.world_mut()
+            .query::<&Organism>()
+            .iter(app.world())
+            .map(|organism| (organism.position, organism.genome.clone()))
+            .collect()
+    }
+
+    fn run_headless(seed: u64, steps: usize) -> Vec<(Vec2, gene::Genome)> {
+        let mut app = headless_app(seed, None);
+        for _ in 0..steps {
+            app.update();
+        }
+        organisms(&mut app)
+    }
+
+    #[test]
+    fn seeded_runs_are_identical() {
+        let steps = Config::default().generation.steps + 20;
+        let first = run_headless(7, steps);
+        assert!(!first.is_empty());
+        assert!(first == run_headless(7, steps));
+        assert!(first != run_headless(8, steps));
+    }
+
+    #[test]
+    fn resume_from_snapshot() {
+        let mut app = headless_app(11, None);
+        for _ in 0..Config::default().generation.steps - 10 {
+            app.update();
+        }
+
+        let path = std::env::temp_dir().join("evo_resume_from_snapshot.evo");
+        Snapshot::capture(app.world_mut()).save(&path).unwrap();
+
+        //run across a generation boundary
+        for _ in 0..20 {
+            app.update();
+        }
+
+        let mut resumed = headless_app(
+            99,
+            Some(Snapshot::load(&path, &Config::default().brain).unwrap()),
+        );
+        for _ in 0..20 {
+            resumed.update();
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(organisms(&mut app) == organisms(&mut resumed));
+        assert!(resumed.world().resource::<Generation>().count == 1);
+    }
+
+    #[test]
+    fn species_ignore_query_order() {
+        let mut config = Config::default();
+        config.species.enabled = true;
+        let mut app = headless_app_with(5, config.clone(), None);
+        for _ in 0..10 {
+            app.update();
+        }
+
+        //sort the organisms of the same snapshot into species again, spawned in opposite orders
+        let species = |reverse: bool, app: &mut App| {
+            let mut snapshot = Snapshot::capture(app.world_mut());
+            snapshot.species = SpeciesTracker::default();
+            if reverse {
+                snapshot.organisms.reverse();
+            }
+            let mut resumed = headless_app_with(5, config.clone(), Some(snapshot));
+            resumed.update();
+            let mut species: Vec<(i32, i32, Option<usize>)> = resumed
+                .world_mut()
+                .query::<&Organism>()
+                .iter(resumed.world())
+                .map(|organism| {
+                    let (x, y) = utils::vec2_to_i32(organism.position);
+                    (x, y, organism.species)
+                })
+                .collect();
+            species.sort();
+            species
+        };
+        let forward = species(false, &mut app);
+        assert!(forward.iter().all(|(_, _, id)| id.is_some()));
+        assert!(forward == species(true, &mut app));
+    }
+
+    #[test]
+    fn species_before_the_first_step() {
+        let mut config = Config::default();
+        config.species.enabled = true;
+        config.species.color_by_species = true;
+        let startup_species = |app: &mut App| {
+            app.world_mut().run_schedule(Startup);
+            app.world_mut()
+                .query::<&Organism>()
+                .iter(app.world())
+                .map(|organism| organism.species)
+                .collect::<Vec<_>>()
+        };
+
+        let mut app = headless_app_with(5, config.clone(), None);
+        let species = startup_species(&mut app);
+        assert!(species.len() == 200 && species.iter().all(Option::is_some));
+
+        //a snapshot saved before its organisms were sorted into species
+        let mut snapshot = Snapshot::capture(app.world_mut());
+        snapshot.species = SpeciesTracker::default();
+        for organism in snapshot.organisms.iter_mut() {
+            organism.species = None;
+        }
+        let mut resumed = headless_app_with(5, config, Some(snapshot));
+        assert!(startup_species(&mut resumed) == species);
+    }
+
+    #[test]
+    fn organisms_starve() {
+        let mut config = Config::default();
+        config.energy = config::EnergyConfig {
+            enabled: true,
+            initial: 2.0,
+            step_cost: 1.0,
+            initial_food: 0.0,
+            regrowth: 0.0,
+            ..config::EnergyConfig::default()
+        };
+        let mut app = headless_app_with(3, config, None);
+
+        //startup and the first step
+        app.update();
+        assert!(organisms(&mut app).len() == 200);
+        app.update();
+        assert!(organisms(&mut app).is_empty());
+        let environment = app.world().resource::<Environment>();
+        assert!(environment.organisms.data.iter().all(|id| *id == 0));
+    }
+
+    #[test]
+    fn eating_food() {
+        let mut config = Config::default();
+        config.energy = config::EnergyConfig {
+            enabled: true,
+            initial_food: 0.5,
+            regrowth: 0.0,
+            ..config::EnergyConfig::default()
+        };
+        let mut app = headless_app_with(3, config, None);
+        app.update();
+
+        //food under every organism has been eaten
+        let environment = app.world().resource::<Environment>();
+        let cells = environment
+            .organisms
+            .data
+            .iter()
+            .zip(&environment.food.data);
+        assert!(cells.clone().all(|(id, food)| *id == 0 || !food));
+        assert!(cells.clone().any(|(_, food)| *food));
+        let fed = app
+            .world_mut()
+            .query::<&Organism>()
+            .iter(app.world())
+            .filter(|organism| organism.energy > 100.0)
+            .count();
+        assert!(fed > 0);
+    }
+
+    #[test]
+    fn pause_and_advance() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut app = headless_app(5, None);
+        //startup and the first step
+        app.update();
+        let mut speed = SimulationSpeed::new(0.01);
+        speed.step();
+        app.insert_resource(speed);
+        let step = |app: &App| app.world().resource::<Generation>().step;
+
+        app.world_mut().run_system_once(run_simulation);
+        assert!(step(&app) == 2);
+        app.world_mut().run_system_once(run_simulation);
+        assert!(step(&app) == 2);
+
+        //the rest of the generation runs over as many frames as it takes
+        app.world_mut()
+            .resource_mut::<SimulationSpeed>()
+            .next_generation(0);
+        while app.world().resource::<SimulationSpeed>().advance.is_some() {
+            app.world_mut().run_system_once(run_simulation);
+        }
+        assert!(app.world().resource::<Generation>().count == 1);
+        assert!(step(&app) == 0);
+        assert!(app.world().resource::<SimulationSpeed>().paused);
+    }
 }
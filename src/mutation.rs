@@ -0,0 +1,318 @@
+use crate::{
+    gene::{Gene, Genome},
+    neural::ConnectionPacked,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+//mutation operators acting on the genes of a genome. All rates are per gene probabilities.
+//
+//invalid gene policy: a mutated gene whose layer or neuron index no longer fits the network
+//is repaired by wrapping the index back into range (index % size). If the repaired gene feeds
+//into the input layer, or doesn't feed forward (the from layer isn't before the to layer) while
+//recurrent connections are disabled, it is discarded. Bias genes and activation genes (see
+//ConnectionPacked::from_activation) are discarded when they land on an input neuron,
+//activation genes are only kept while activations evolve and bias genes while biases are on.
+//A bit flip can turn a connection into either of them, those are discarded the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MutationRates {
+    //flip a single random bit of the packed 32 bit gene
+    pub bit_flip: f64,
+    //nudge the weight by up to +/- weight_perturbation_size
+    pub weight_perturbation: f64,
+    pub weight_perturbation_size: f32,
+    //insert a copy of the gene right after it
+    pub duplication: f64,
+    //remove the gene
+    pub deletion: f64,
+}
+
+impl Default for MutationRates {
+    fn default() -> Self {
+        MutationRates {
+            bit_flip: 0.001,
+            weight_perturbation: 0.01,
+            weight_perturbation_size: 0.5,
+            duplication: 0.001,
+            deletion: 0.001,
+        }
+    }
+}
+
+pub fn flip_bit(gene: &Gene, rng: &mut impl Rng) -> Gene {
+    Gene::from_bits(gene.bits() ^ (1 << rng.gen_range(0..32)))
+}
+
+pub fn perturb_weight(gene: &Gene, size: f32, rng: &mut impl Rng) -> Gene {
+    let Ok(mut packed) = ConnectionPacked::try_from(gene) else {
+        return gene.clone();
+    };
+    let delta = (rng.gen_range(-size..=size) * 1000.0).round() as i16;
+    packed.weight = packed.weight.saturating_add(delta);
+    packed.into()
+}
+
+//the kinds of genes kept by mutation besides forward connections
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GeneKinds {
+    pub recurrent: bool,
+    pub activations: bool,
+    pub biases: bool,
+}
+
+//wraps the layer and neuron indices of a gene into the network, None if the result
+//isn't a gene of the allowed kinds
+pub fn repair_gene(gene: &Gene, layers: &[usize], kinds: GeneKinds) -> Option<Gene> {
+    let mut packed = ConnectionPacked::try_from(gene).ok()?;
+    let wrap = |(layer, neuron): (usize, usize)| {
+        let layer = layer % layers.len();
+        let neuron = neuron % layers[layer];
+        (layer, neuron)
+    };
+
+    let from = wrap(packed.get_from());
+    let activation = packed.get_activation().is_some();
+    let bias = packed.get_bias().is_some();
+    if activation || bias {
+        if from.0 == 0 || (activation && !kinds.activations) || (bias && !kinds.biases) {
+            return None;
+        }
+        packed.from = ((from.0 as u8) << 4) | from.1 as u8;
+        return Some(packed.into());
+    }
+
+    let to = wrap(packed.get_to());
+    if to.0 == 0 || (from.0 >= to.0 && !kinds.recurrent) {
+        return None;
+    }
+
+    packed.from = ((from.0 as u8) << 4) | from.1 as u8;
+    packed.to = ((to.0 as u8) << 4) | to.1 as u8;
+    Some(packed.into())
+}
+
+pub fn mutate(
+    genome: &Genome,
+    rates: &MutationRates,
+    kinds: GeneKinds,
+    rng: &mut impl Rng,
+) -> Genome {
+    let layers = genome.layers();
+    let mut genes: Vec<Gene> = Vec::with_capacity(genome.length());
+
+    for gene in genome.genes() {
+        if rng.gen_bool(rates.deletion) {
+            continue;
+        }
+
+        let mut gene = gene.clone();
+        if rng.gen_bool(rates.bit_flip) {
+            gene = flip_bit(&gene, rng);
+        }
+        if rng.gen_bool(rates.weight_perturbation) {
+            gene = perturb_weight(&gene, rates.weight_perturbation_size, rng);
+        }
+
+        let Some(gene) = repair_gene(&gene, layers, kinds) else {
+            continue;
+        };
+
+        if rng.gen_bool(rates.duplication) {
+            genes.push(gene.clone());
+        }
+        genes.push(gene);
+    }
+
+    Genome::from_genes(layers.to_vec(), genes)
+}
+
+mod tests {
+    use super::*;
+    use crate::neural::{Activation, NeuralNet, WEIGHT_RANGE};
+
+    fn random_genome() -> Genome {
+        let mut test_net = NeuralNet::new(vec![3, 2, 2]);
+        test_net.init_random_connections(8, (-WEIGHT_RANGE, WEIGHT_RANGE), &mut rand::thread_rng());
+        test_net.into()
+    }
+
+    fn no_mutation() -> MutationRates {
+        MutationRates {
+            bit_flip: 0.0,
+            weight_perturbation: 0.0,
+            weight_perturbation_size: 0.0,
+            duplication: 0.0,
+            deletion: 0.0,
+        }
+    }
+
+    #[test]
+    fn zero_rates_keep_genome() {
+        let genome = random_genome();
+        let mutated = mutate(
+            &genome,
+            &no_mutation(),
+            GeneKinds::default(),
+            &mut rand::thread_rng(),
+        );
+        assert!(mutated == genome);
+    }
+
+    #[test]
+    fn duplication_and_deletion() {
+        let genome = random_genome();
+        let rates = MutationRates {
+            duplication: 1.0,
+            ..no_mutation()
+        };
+        let mutated = mutate(
+            &genome,
+            &rates,
+            GeneKinds::default(),
+            &mut rand::thread_rng(),
+        );
+        assert!(mutated.length() == genome.length() * 2);
+
+        let rates = MutationRates {
+            deletion: 1.0,
+            ..no_mutation()
+        };
+        let mutated = mutate(
+            &genome,
+            &rates,
+            GeneKinds::default(),
+            &mut rand::thread_rng(),
+        );
+        assert!(mutated.length() == 0);
+        assert!(NeuralNet::try_from(&mutated).is_ok());
+    }
+
+    #[test]
+    fn weight_perturbation_keeps_connection() {
+        let genome = random_genome();
+        let rates = MutationRates {
+            weight_perturbation: 1.0,
+            weight_perturbation_size: 0.5,
+            ..no_mutation()
+        };
+        let mutated = mutate(
+            &genome,
+            &rates,
+            GeneKinds::default(),
+            &mut rand::thread_rng(),
+        );
+        assert!(mutated.length() == genome.length());
+        for (original, mutated) in genome.genes().iter().zip(mutated.genes()) {
+            let original = ConnectionPacked::try_from(original).unwrap();
+            let mutated = ConnectionPacked::try_from(mutated).unwrap();
+            assert!(original.from == mutated.from && original.to == mutated.to);
+            assert!((original.weight as i32 - mutated.weight as i32).abs() <= 500);
+        }
+    }
+
+    #[test]
+    fn bit_flips_stay_valid() {
+        let mut rng = rand::thread_rng();
+        let rates = MutationRates {
+            bit_flip: 1.0,
+            ..no_mutation()
+        };
+        let mut genome = random_genome();
+        for _ in 0..100 {
+            genome = mutate(&genome, &rates, GeneKinds::default(), &mut rng);
+            assert!(NeuralNet::try_from(&genome).is_ok());
+        }
+    }
+
+    #[test]
+    fn bit_flips_keep_biases_off() {
+        // flipping the to layer of a connection into layer 1 of a [3, 2] network down to 0
+        // leaves neuron 9, which makes it a bias gene
+        let connection: Gene = ConnectionPacked {
+            from: 0x10,
+            to: 0x19,
+            weight: 100,
+        }
+        .into();
+        let flipped = Gene::from_bits(connection.bits() ^ (1 << 20));
+        let flipped = ConnectionPacked::try_from(&flipped).unwrap();
+        assert!(flipped.get_bias() == Some(((1, 0), 0.1)));
+        assert!(repair_gene(&flipped.into(), &[3, 2], GeneKinds::default()).is_none());
+
+        let mut rng = rand::thread_rng();
+        let rates = MutationRates {
+            bit_flip: 1.0,
+            ..no_mutation()
+        };
+        let mut genome = random_genome();
+        for _ in 0..100 {
+            genome = mutate(&genome, &rates, GeneKinds::default(), &mut rng);
+            for gene in genome.genes() {
+                let packed = ConnectionPacked::try_from(gene).unwrap();
+                assert!(packed.get_bias().is_none() && packed.get_activation().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn repair_wraps_indices() {
+        let all = GeneKinds {
+            recurrent: true,
+            activations: true,
+            biases: true,
+        };
+        let repair = |gene: &Gene, kinds: GeneKinds| {
+            repair_gene(gene, &[3, 2], kinds).map(|gene| ConnectionPacked::try_from(&gene).unwrap())
+        };
+
+        // from (0, 5) to (1, 3) in a [3, 2] network wraps to (0, 2) -> (1, 1)
+        let gene: Gene = ConnectionPacked {
+            from: 0x05,
+            to: 0x13,
+            weight: 100,
+        }
+        .into();
+        let repaired = repair(&gene, GeneKinds::default()).unwrap();
+        assert!(repaired.get_from() == (0, 2));
+        assert!(repaired.get_to() == (1, 1));
+
+        // from layer 3 wraps to layer 1 which is the output layer, so it's discarded
+        let gene: Gene = ConnectionPacked {
+            from: 0x30,
+            to: 0x10,
+            weight: 100,
+        }
+        .into();
+        assert!(repair(&gene, GeneKinds::default()).is_none());
+
+        // unless recurrent connections are allowed, then it's a self connection
+        let repaired = repair(&gene, all).unwrap();
+        assert!(repaired.get_from() == (1, 0) && repaired.get_to() == (1, 0));
+
+        // nothing feeds into the input layer, to layer 2 wraps to layer 0
+        let gene: Gene = ConnectionPacked {
+            from: 0x10,
+            to: 0x20,
+            weight: 100,
+        }
+        .into();
+        assert!(repair(&gene, all).is_none());
+
+        // activation genes are kept while activations evolve, on neurons past the input layer
+        let gene: Gene = ConnectionPacked::from_activation((3, 3), Activation::Relu).into();
+        assert!(repair(&gene, GeneKinds::default()).is_none());
+        let repaired = repair(&gene, all).unwrap();
+        assert!(repaired.get_activation() == Some(((1, 1), Activation::Relu)));
+        let gene: Gene = ConnectionPacked::from_activation((2, 0), Activation::Relu).into();
+        assert!(repair(&gene, all).is_none());
+
+        // bias genes are kept while biases are on, but not on input neurons
+        let gene: Gene = ConnectionPacked::from_bias((1, 3), -0.25).into();
+        assert!(repair(&gene, GeneKinds::default()).is_none());
+        let repaired = repair(&gene, all).unwrap();
+        assert!(repaired.get_bias() == Some(((1, 1), -0.25)));
+        let gene: Gene = ConnectionPacked::from_bias((0, 1), -0.25).into();
+        assert!(repair(&gene, all).is_none());
+    }
+}
@@ -1,23 +1,133 @@
-// use crate::gene::Genome;
+use crate::gene::GenomeError;
 use bevy::reflect::Map;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
+//default range of founder weights, also used to normalise gene colours
 pub const WEIGHT_RANGE: f32 = 2.0;
+//layer and neuron indices are packed into 4 bits each
+pub const MAX_PACKED_INDEX: usize = 16;
+//`to` byte of bias genes, see ConnectionPacked::from_activation
+const BIAS_GENE: u8 = 0x08;
+
+//applied by every neuron past the input layer to the weighted sum of its inputs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    #[default]
+    Tanh,
+    Sigmoid,
+    Relu,
+    //relu with a slope of 0.01 below 0
+    LeakyRelu,
+    Identity,
+    //1 above 0, 0 otherwise
+    Step,
+    Sine,
+    //bell curve peaking at 1 for 0
+    Gaussian,
+}
 
-#[derive(Clone)]
+impl Activation {
+    //in the order of their index in activation genes
+    pub const ALL: [Activation; 8] = [
+        Activation::Tanh,
+        Activation::Sigmoid,
+        Activation::Relu,
+        Activation::LeakyRelu,
+        Activation::Identity,
+        Activation::Step,
+        Activation::Sine,
+        Activation::Gaussian,
+    ];
+
+    pub fn apply(&self, x: f32) -> f32 {
+        match self {
+            Activation::Tanh => NeuralNet::tanh(x),
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Activation::Relu => x.max(0.0),
+            Activation::LeakyRelu => {
+                if x > 0.0 {
+                    x
+                } else {
+                    0.01 * x
+                }
+            }
+            Activation::Identity => x,
+            Activation::Step => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Activation::Sine => x.sin(),
+            Activation::Gaussian => (-x * x).exp(),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        Activation::ALL.iter().position(|a| a == self).unwrap()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NeuralNet {
     pub layers: Vec<Layer>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Connection {
     pub from: (usize, usize),
     pub to: (usize, usize),
     pub weight: f32,
 }
 
+impl Connection {
+    //backwards, same layer and self connections carry the activation of the previous step
+    pub fn is_recurrent(&self) -> bool {
+        self.from.0 >= self.to.0
+    }
+}
+
+//activations of every neuron after the previous step in layer order, the memory recurrent
+//connections read from. Empty until the first step, missing activations count as 0
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "LayeredBrainState", into = "LayeredBrainState")]
+pub struct BrainState {
+    pub activations: Vec<f32>,
+}
+
+//brain states used to be stored per layer, snapshots keep that format: a state is saved as a
+//single layer and the layers are joined when it's loaded
+#[derive(Serialize, Deserialize)]
+struct LayeredBrainState {
+    activations: Vec<Vec<f32>>,
+}
+
+impl From<LayeredBrainState> for BrainState {
+    fn from(state: LayeredBrainState) -> Self {
+        BrainState {
+            activations: state.activations.concat(),
+        }
+    }
+}
+
+impl From<BrainState> for LayeredBrainState {
+    fn from(state: BrainState) -> Self {
+        LayeredBrainState {
+            activations: vec![state.activations],
+        }
+    }
+}
+
+impl BrainState {
+    pub fn get(&self, neuron: usize) -> f32 {
+        self.activations.get(neuron).copied().unwrap_or(0.0)
+    }
+}
+
 impl From<ConnectionPacked> for Connection {
     fn from(conn_packed: ConnectionPacked) -> Connection {
         Connection {
@@ -54,14 +164,53 @@ impl ConnectionPacked {
         ((self.to >> 4) as usize, (self.to & 0x0F) as usize)
     }
 
-    fn from_hex(hex_string: &str) -> Self {
-        let bytes = hex::decode(hex_string).expect("Failed to decode hex");
+    //no connection feeds into the input layer, genes that do set the activation or the bias of
+    //their from neuron instead. A neuron nibble of `to` below 8 is the index into
+    //Activation::ALL, from 8 up the gene is a bias gene holding the bias as its weight
+    pub fn from_activation(neuron: (usize, usize), activation: Activation) -> Self {
+        ConnectionPacked {
+            from: ((neuron.0 as u8) << 4) | (neuron.1 as u8 & 0x0F),
+            to: activation.index() as u8,
+            weight: 0,
+        }
+    }
+
+    pub fn from_bias(neuron: (usize, usize), bias: f32) -> Self {
+        ConnectionPacked {
+            from: ((neuron.0 as u8) << 4) | (neuron.1 as u8 & 0x0F),
+            to: BIAS_GENE,
+            weight: (bias * 1000.0).round() as i16,
+        }
+    }
+
+    //the neuron and bias of a bias gene, None for other genes
+    pub fn get_bias(&self) -> Option<((usize, usize), f32)> {
+        (self.to & 0xF8 == BIAS_GENE).then(|| (self.get_from(), self.weight as f32 / 1000.0))
+    }
+
+    //the neuron and activation of an activation gene, None for other genes
+    pub fn get_activation(&self) -> Option<((usize, usize), Activation)> {
+        let (layer, index) = self.get_to();
+        if layer != 0 {
+            return None;
+        }
+        Activation::ALL
+            .get(index)
+            .map(|activation| (self.get_from(), *activation))
+    }
+
+    pub fn from_hex(hex_string: &str) -> Result<Self, GenomeError> {
+        let bytes =
+            hex::decode(hex_string).map_err(|_| GenomeError::InvalidHex(hex_string.to_string()))?;
+        if bytes.len() != 4 {
+            return Err(GenomeError::InvalidLength(hex_string.to_string()));
+        }
         // Unpack the values from bytes
         let from = bytes[0];
         let to = bytes[1];
         let weight = i16::from_le_bytes([bytes[2], bytes[3]]);
 
-        ConnectionPacked { from, to, weight }
+        Ok(ConnectionPacked { from, to, weight })
     }
 }
 
@@ -71,7 +220,7 @@ impl From<Connection> for ConnectionPacked {
             // Packing x and y coordinates into a single u8
             from: ((conn.from.0 as u8) << 4) | (conn.from.1 as u8 & 0x0F),
             to: ((conn.to.0 as u8) << 4) | (conn.to.1 as u8 & 0x0F),
-            weight: (conn.weight * 1000.0) as i16, // Convert weight to i16
+            weight: (conn.weight * 1000.0).round() as i16, // Convert weight to i16
         }
     }
 }
@@ -86,6 +235,23 @@ impl NeuralNet {
         Self { layers }
     }
 
+    pub fn definition(&self) -> Vec<usize> {
+        self.layers
+            .iter()
+            .map(|layer| layer.neurons.len())
+            .collect()
+    }
+
+    //a connection is valid if both neurons exist and it doesn't feed into the input layer,
+    //connections that don't feed forward are recurrent
+    pub fn is_valid_connection(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        from.0 < self.layers.len()
+            && 0 < to.0
+            && to.0 < self.layers.len()
+            && from.1 < self.layers[from.0].neurons.len()
+            && to.1 < self.layers[to.0].neurons.len()
+    }
+
     pub fn get_summary_connections(&self) -> Vec<ConnectionPacked> {
         let mut direct_connections: Vec<Connection> = vec![];
         let last_layer_index = self.layers.len() - 1;
@@ -124,53 +290,121 @@ impl NeuralNet {
     }
 
     pub fn tanh(x: f32) -> f32 {
-        (x.exp() - (-x).exp()) / (x.exp() + (-x).exp())
+        let (positive, negative) = (x.exp(), (-x).exp());
+        (positive - negative) / (positive + negative)
+    }
+
+    //index of every neuron in layer order, as used by BrainState and CompiledNet
+    fn neuron_index(&self, (layer, neuron): (usize, usize)) -> usize {
+        self.layers[..layer]
+            .iter()
+            .map(|layer| layer.neurons.len())
+            .sum::<usize>()
+            + neuron
     }
 
+    //a forward pass without memory, recurrent connections read 0
     pub fn forward(&self, input: Vec<f32>) -> Vec<f32> {
+        self.step(input, &mut BrainState::default())
+    }
+
+    //a forward pass where recurrent connections read the activations of the previous step
+    //from `state`, which is replaced with the activations of this step. Organisms step the
+    //CompiledNet of their brain instead, this is what it's checked against
+    pub fn step(&self, input: Vec<f32>, state: &mut BrainState) -> Vec<f32> {
         let mut accumulator: HashMap<(usize, usize), f32> = HashMap::new();
+        self.for_each_neuron(|_, _, neuron| {
+            for connection in neuron.connections.iter().filter(|c| c.is_recurrent()) {
+                *accumulator.entry(connection.to).or_insert(0.0) +=
+                    state.get(self.neuron_index(connection.from)) * connection.weight;
+            }
+        });
+
+        //neurons are reached in layer order, so every forward connection into a neuron has
+        //been summed by the time it's reached
         self.for_each_neuron(|layer_index, neuron_index, neuron| {
             let id = (layer_index, neuron_index);
-            let mut input_value = *accumulator.entry(id).or_insert(0.0);
-            for connection in &neuron.connections {
-                //if if on the input layer use input values as current sum
-                let to_id = connection.to;
-                if layer_index == 0 {
-                    input_value = input[id.1];
-                    accumulator.insert(id, input_value);
-                }
-                let current_output = accumulator.entry(to_id).or_insert(0.0);
-                *current_output += input_value * connection.weight;
+            let value = match layer_index {
+                0 => input[neuron_index],
+                _ => self
+                    .activation(id)
+                    .apply(accumulator.get(&id).copied().unwrap_or(0.0) + neuron.bias),
+            };
+            accumulator.insert(id, value);
+
+            for connection in neuron.connections.iter().filter(|c| !c.is_recurrent()) {
+                *accumulator.entry(connection.to).or_insert(0.0) += value * connection.weight;
             }
+        });
 
-            //once we've reached a node we can assume we've collated all it's entry connections
-            if layer_index != 0 {
-                let current_output = accumulator.get_mut(&id).unwrap();
-                *current_output = NeuralNet::tanh(*current_output);
-            }
+        state.activations.clear();
+        self.for_each_neuron(|layer_index, neuron_index, _| {
+            state
+                .activations
+                .push(accumulator[&(layer_index, neuron_index)]);
         });
 
-        let output_layer_index = self.layers.len() - 1;
-        let output_layer_size = self.layers[output_layer_index].neurons.len();
+        let outputs = self.layers.last().unwrap().neurons.len();
+        state.activations[state.activations.len() - outputs..].to_vec()
+    }
 
-        //return the output layer (tanh)
-        return (0..output_layer_size)
-            .map(|x| {
-                let id = (output_layer_index, x);
-                let output = accumulator.get(&id).unwrap();
-                NeuralNet::tanh(*output)
-            })
-            .collect();
+    //the activation function of the neuron, its own if the genome sets one or its layer's
+    pub fn activation(&self, (layer, neuron): (usize, usize)) -> Activation {
+        let layer = &self.layers[layer];
+        layer.neurons[neuron].activation.unwrap_or(layer.activation)
+    }
+
+    //sets the activation function of every layer after the input layer, in order. Layers
+    //past the end of `activations` keep theirs
+    pub fn set_layer_activations(&mut self, activations: &[Activation]) {
+        for (layer, activation) in self.layers.iter_mut().skip(1).zip(activations) {
+            layer.activation = *activation;
+        }
+    }
+
+    pub fn set_bias(&mut self, (layer, neuron): (usize, usize), bias: f32) {
+        self.layers[layer].neurons[neuron].bias = bias;
     }
 
+    pub fn set_neuron_activation(
+        &mut self,
+        (layer, neuron): (usize, usize),
+        activation: Activation,
+    ) {
+        self.layers[layer].neurons[neuron].activation = Some(activation);
+    }
+
+    //every neuron can connect to every neuron of a later layer
     pub fn max_connections(&self) -> usize {
-        self.layers.iter().fold(0, |acc, layer| {
-            acc + layer.neurons.len() * layer.neurons.len()
-        })
+        let sizes: Vec<usize> = self
+            .layers
+            .iter()
+            .map(|layer| layer.neurons.len())
+            .collect();
+        (0..sizes.len())
+            .map(|from| sizes[from] * sizes[from + 1..].iter().sum::<usize>())
+            .sum()
+    }
+
+    //every neuron past the input layer can connect to itself and every neuron of the same
+    //and earlier layers, except the input layer
+    pub fn max_recurrent_connections(&self) -> usize {
+        let sizes: Vec<usize> = self
+            .layers
+            .iter()
+            .map(|layer| layer.neurons.len())
+            .collect();
+        (1..sizes.len())
+            .map(|from| sizes[from] * sizes[1..=from].iter().sum::<usize>())
+            .sum()
     }
 
-    pub fn init_random_connections(&mut self, num_connections: usize, weight_range: (f32, f32)) {
-        let mut rng = rand::thread_rng();
+    pub fn init_random_connections(
+        &mut self,
+        num_connections: usize,
+        weight_range: (f32, f32),
+        rng: &mut impl Rng,
+    ) {
         let mut initialized_connections: HashSet<(usize, usize, usize, usize)> = HashSet::new();
         let max_connections = self.max_connections();
 
@@ -199,6 +433,57 @@ impl NeuralNet {
         }
     }
 
+    pub fn init_random_recurrent_connections(
+        &mut self,
+        num_connections: usize,
+        weight_range: (f32, f32),
+        rng: &mut impl Rng,
+    ) {
+        let mut initialized_connections: HashSet<(usize, usize, usize, usize)> = HashSet::new();
+        if num_connections > self.max_recurrent_connections() {
+            panic!("Number of recurrent connections must be less than the maximum possible")
+        }
+
+        while initialized_connections.len() < num_connections {
+            let layer_from = rng.gen_range(1..self.layers.len());
+            let neuron_from = rng.gen_range(0..self.layers[layer_from].neurons.len());
+
+            let layer_to = rng.gen_range(1..=layer_from);
+            let neuron_to = rng.gen_range(0..self.layers[layer_to].neurons.len());
+
+            if !initialized_connections.insert((layer_from, neuron_from, layer_to, neuron_to)) {
+                continue;
+            }
+
+            let connection_weight = rng.gen_range(weight_range.0..weight_range.1);
+            self.add_connection(
+                (layer_from, neuron_from),
+                (layer_to, neuron_to),
+                connection_weight,
+            );
+        }
+    }
+
+    //gives random neurons past the input layer a bias
+    pub fn init_random_biases(
+        &mut self,
+        num_biases: usize,
+        bias_range: (f32, f32),
+        rng: &mut impl Rng,
+    ) {
+        let neurons: Vec<(usize, usize)> = (1..self.layers.len())
+            .flat_map(|layer| (0..self.layers[layer].neurons.len()).map(move |n| (layer, n)))
+            .collect();
+        if num_biases > neurons.len() {
+            panic!("Number of biases must be less than the number of neurons past the input layer")
+        }
+
+        for neuron in rand::seq::index::sample(rng, neurons.len(), num_biases) {
+            let bias = rng.gen_range(bias_range.0..bias_range.1);
+            self.set_bias(neurons[neuron], bias);
+        }
+    }
+
     pub fn add_connection(&mut self, from: (usize, usize), to: (usize, usize), weight: f32) {
         if from.0 < self.layers.len()
             && to.0 < self.layers.len()
@@ -212,9 +497,97 @@ impl NeuralNet {
     }
 }
 
-#[derive(Clone)]
+//a NeuralNet flattened into arrays, built once per brain so stepping it doesn't allocate.
+//Neurons are numbered in layer order (see BrainState) and everything is summed in the same
+//order as NeuralNet::step, so the results are bit-identical
+#[derive(Clone, Debug, Default)]
+pub struct CompiledNet {
+    inputs: usize,
+    outputs: usize,
+    //of every neuron, unused for the input layer
+    activations: Vec<Activation>,
+    biases: Vec<f32>,
+    recurrent: Vec<CompiledConnection>,
+    //forward connections sorted by from neuron, those of neuron n start at forward_starts[n]
+    forward: Vec<CompiledConnection>,
+    forward_starts: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CompiledConnection {
+    from: usize,
+    to: usize,
+    weight: f32,
+}
+
+impl From<&NeuralNet> for CompiledNet {
+    fn from(net: &NeuralNet) -> Self {
+        let mut compiled = CompiledNet {
+            inputs: net.layers[0].neurons.len(),
+            outputs: net.layers.last().unwrap().neurons.len(),
+            ..Default::default()
+        };
+        net.for_each_neuron(|layer_index, neuron_index, neuron| {
+            let id = (layer_index, neuron_index);
+            compiled.activations.push(net.activation(id));
+            compiled.biases.push(neuron.bias);
+            compiled.forward_starts.push(compiled.forward.len());
+            for connection in &neuron.connections {
+                let compiled_connection = CompiledConnection {
+                    from: net.neuron_index(connection.from),
+                    to: net.neuron_index(connection.to),
+                    weight: connection.weight,
+                };
+                match connection.is_recurrent() {
+                    true => compiled.recurrent.push(compiled_connection),
+                    false => compiled.forward.push(compiled_connection),
+                }
+            }
+        });
+        compiled.forward_starts.push(compiled.forward.len());
+        compiled
+    }
+}
+
+impl CompiledNet {
+    //same as NeuralNet::step, `sums` is a scratch buffer that can be shared between brains.
+    //Returns the activations of the output layer
+    pub fn step<'a>(
+        &self,
+        input: &[f32],
+        state: &'a mut BrainState,
+        sums: &mut Vec<f32>,
+    ) -> &'a [f32] {
+        let neurons = self.biases.len();
+        sums.clear();
+        sums.resize(neurons, 0.0);
+        for connection in &self.recurrent {
+            sums[connection.to] += state.get(connection.from) * connection.weight;
+        }
+
+        state.activations.resize(neurons, 0.0);
+        for neuron in 0..neurons {
+            let value = match neuron < self.inputs {
+                true => input[neuron],
+                false => self.activations[neuron].apply(sums[neuron] + self.biases[neuron]),
+            };
+            state.activations[neuron] = value;
+
+            let connections = self.forward_starts[neuron]..self.forward_starts[neuron + 1];
+            for connection in &self.forward[connections] {
+                sums[connection.to] += value * connection.weight;
+            }
+        }
+
+        &state.activations[neurons - self.outputs..]
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Layer {
     pub neurons: Vec<Neuron>,
+    //of every neuron in the layer that doesn't set its own, unused on the input layer
+    pub activation: Activation,
 }
 
 impl Layer {
@@ -225,16 +598,25 @@ impl Layer {
         let neurons = (0..length)
             .map(|_| Neuron {
                 connections: Vec::new(),
+                activation: None,
+                bias: 0.0,
             })
             .collect();
-        Layer { neurons }
+        Layer {
+            neurons,
+            activation: Activation::default(),
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Neuron {
     //first index is the layer and the second index in the neuron
     pub connections: Vec<Connection>,
+    //overrides the activation of the layer, set by activation genes
+    pub activation: Option<Activation>,
+    //added to the weighted sum of the inputs, set by bias genes
+    pub bias: f32,
 }
 
 mod tests {
@@ -250,7 +632,7 @@ mod tests {
 
         let packed: ConnectionPacked = connection.clone().into();
         let hex = packed.to_hex();
-        let repacked = ConnectionPacked::from_hex(&hex);
+        let repacked = ConnectionPacked::from_hex(&hex).unwrap();
         let unpacked = Connection::from(repacked);
         assert!(connection.from == unpacked.from);
         assert!(connection.to == unpacked.to);
@@ -266,4 +648,232 @@ mod tests {
         let output = test_net.forward(vec![0.1, 0.2]);
         assert!(output == vec![0.45658463, 0.0]);
     }
+
+    //the forward_pass network with a second output fed the negated hidden neuron
+    fn activation_forward_pass(activation: Activation, expected: [f32; 2]) {
+        let mut test_net = NeuralNet::new(vec![2, 3, 2]);
+        test_net.add_connection((0, 0), (1, 1), 1.2);
+        test_net.add_connection((0, 1), (1, 1), 2.1);
+        test_net.add_connection((1, 1), (2, 0), 1.0);
+        test_net.add_connection((1, 1), (2, 1), -1.0);
+        test_net.set_layer_activations(&[activation, activation]);
+        let output = test_net.forward(vec![0.1, 0.2]);
+        assert!(
+            output
+                .iter()
+                .zip(expected)
+                .all(|(output, expected)| (output - expected).abs() < 1e-6),
+            "{:?} {:?}",
+            activation,
+            output
+        );
+    }
+
+    #[test]
+    fn forward_pass_tanh() {
+        activation_forward_pass(Activation::Tanh, [0.4565847, -0.4565847]);
+    }
+
+    #[test]
+    fn forward_pass_sigmoid() {
+        activation_forward_pass(Activation::Sigmoid, [0.6529003, 0.3470997]);
+    }
+
+    #[test]
+    fn forward_pass_relu() {
+        activation_forward_pass(Activation::Relu, [0.54, 0.0]);
+    }
+
+    #[test]
+    fn forward_pass_leaky_relu() {
+        activation_forward_pass(Activation::LeakyRelu, [0.54, -0.0054]);
+    }
+
+    #[test]
+    fn forward_pass_identity() {
+        activation_forward_pass(Activation::Identity, [0.54, -0.54]);
+    }
+
+    #[test]
+    fn forward_pass_step() {
+        activation_forward_pass(Activation::Step, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn forward_pass_sine() {
+        activation_forward_pass(Activation::Sine, [0.4917827, -0.4917827]);
+    }
+
+    #[test]
+    fn forward_pass_gaussian() {
+        activation_forward_pass(Activation::Gaussian, [0.5722899, 0.5722899]);
+    }
+
+    #[test]
+    fn neuron_activation() {
+        let mut test_net = NeuralNet::new(vec![1, 2]);
+        test_net.add_connection((0, 0), (1, 0), -1.0);
+        test_net.add_connection((0, 0), (1, 1), -1.0);
+        test_net.set_layer_activations(&[Activation::Identity]);
+        test_net.set_neuron_activation((1, 1), Activation::Relu);
+        assert!(test_net.activation((1, 0)) == Activation::Identity);
+        assert!(test_net.forward(vec![0.5]) == vec![-0.5, 0.0]);
+
+        let packed = ConnectionPacked::from_activation((1, 1), Activation::Relu);
+        assert!(packed.get_activation() == Some(((1, 1), Activation::Relu)));
+        assert!(ConnectionPacked::from(Connection {
+            from: (0, 0),
+            to: (1, 1),
+            weight: 1.0
+        })
+        .get_activation()
+        .is_none());
+    }
+
+    #[test]
+    fn neuron_bias() {
+        let mut test_net = NeuralNet::new(vec![1, 2]);
+        test_net.add_connection((0, 0), (1, 0), 1.0);
+        test_net.set_layer_activations(&[Activation::Identity]);
+        test_net.set_bias((1, 0), 0.25);
+        test_net.set_bias((1, 1), -0.5);
+        assert!(test_net.forward(vec![0.5]) == vec![0.75, -0.5]);
+
+        let packed = ConnectionPacked::from_bias((1, 1), -0.5);
+        assert!(packed.get_bias() == Some(((1, 1), -0.5)));
+        assert!(packed.get_activation().is_none());
+
+        let mut test_net = NeuralNet::new(vec![3, 2, 2]);
+        test_net.init_random_biases(4, (-1.0, 1.0), &mut rand::thread_rng());
+        assert!(test_net.layers[0]
+            .neurons
+            .iter()
+            .all(|neuron| neuron.bias == 0.0));
+        let biased = test_net.layers[1..]
+            .iter()
+            .flat_map(|layer| &layer.neurons)
+            .filter(|neuron| neuron.bias != 0.0)
+            .count();
+        assert!(biased == 4);
+    }
+
+    #[test]
+    fn recurrent_connections() {
+        let mut test_net = NeuralNet::new(vec![1, 1, 1]);
+        test_net.add_connection((0, 0), (1, 0), 1.0);
+        test_net.add_connection((1, 0), (1, 0), 1.0);
+        test_net.add_connection((2, 0), (1, 0), -0.5);
+        test_net.add_connection((1, 0), (2, 0), 1.0);
+
+        let mut state = BrainState::default();
+        let first = test_net.step(vec![0.5], &mut state);
+        assert!(first == test_net.forward(vec![0.5]));
+        let hidden = NeuralNet::tanh(0.5);
+        let output = NeuralNet::tanh(hidden);
+        assert!(state.activations == vec![0.5, hidden, output]);
+        assert!(first == vec![output]);
+
+        //the hidden neuron now also sees its own and the output's previous activation
+        let second = test_net.step(vec![0.5], &mut state);
+        let hidden = NeuralNet::tanh(0.5 + hidden - 0.5 * output);
+        assert!(state.get(1) == hidden);
+        assert!(second == vec![NeuralNet::tanh(hidden)]);
+        assert!(second != first);
+
+        assert!(test_net.is_valid_connection((2, 0), (1, 0)));
+        assert!(!test_net.is_valid_connection((1, 0), (0, 0)));
+        assert!(test_net.max_recurrent_connections() == 3);
+        test_net.init_random_recurrent_connections(3, (-1.0, 1.0), &mut rand::thread_rng());
+    }
+
+    #[test]
+    fn brain_state_format() {
+        //as the layered state of a [1, 1, 1] network was saved
+        let layered = bincode::serialize(&vec![vec![0.5f32], vec![0.25], vec![-1.0]]).unwrap();
+        let state: BrainState = bincode::deserialize(&layered).unwrap();
+        assert!(state.activations == vec![0.5, 0.25, -1.0]);
+
+        let saved = bincode::serialize(&state).unwrap();
+        assert!(bincode::deserialize::<BrainState>(&saved).unwrap() == state);
+        assert!(
+            bincode::deserialize::<BrainState>(
+                &bincode::serialize(&BrainState::default()).unwrap()
+            )
+            .unwrap()
+                == BrainState::default()
+        );
+    }
+
+    //random brains with every gene kind, stepped a few times from random inputs
+    fn random_brains(count: usize, rng: &mut impl Rng) -> Vec<(NeuralNet, Vec<Vec<f32>>)> {
+        (0..count)
+            .map(|_| {
+                let mut net = NeuralNet::new(vec![8, 6, 6, 4]);
+                net.init_random_connections(40, (-WEIGHT_RANGE, WEIGHT_RANGE), rng);
+                net.init_random_recurrent_connections(6, (-WEIGHT_RANGE, WEIGHT_RANGE), rng);
+                net.init_random_biases(4, (-WEIGHT_RANGE, WEIGHT_RANGE), rng);
+                net.set_layer_activations(&[Activation::Relu, Activation::Sigmoid]);
+                for _ in 0..3 {
+                    let neuron = (rng.gen_range(1..4), rng.gen_range(0..4));
+                    let activation = Activation::ALL[rng.gen_range(0..Activation::ALL.len())];
+                    net.set_neuron_activation(neuron, activation);
+                }
+                let inputs = (0..5)
+                    .map(|_| (0..8).map(|_| rng.gen_range(0.0..1.0)).collect())
+                    .collect();
+                (net, inputs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compiled_step_matches() {
+        let bits = |values: &[f32]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        let mut sums = Vec::new();
+        for (net, inputs) in random_brains(200, &mut rand::thread_rng()) {
+            let compiled = CompiledNet::from(&net);
+            let (mut state, mut compiled_state) = (BrainState::default(), BrainState::default());
+            for input in inputs {
+                let output = net.step(input.clone(), &mut state);
+                let compiled_output = compiled.step(&input, &mut compiled_state, &mut sums);
+                assert!(bits(compiled_output) == bits(&output));
+                assert!(bits(&compiled_state.activations) == bits(&state.activations));
+            }
+        }
+    }
+
+    //cargo test --release compiled_step_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn compiled_step_benchmark() {
+        let brains = random_brains(10_000, &mut rand::thread_rng());
+
+        let start = std::time::Instant::now();
+        for (net, inputs) in &brains {
+            let mut state = BrainState::default();
+            for input in inputs {
+                std::hint::black_box(net.step(input.clone(), &mut state));
+            }
+        }
+        let interpreted = start.elapsed();
+
+        let compiled: Vec<CompiledNet> = brains.iter().map(|(net, _)| net.into()).collect();
+        let mut sums = Vec::new();
+        let start = std::time::Instant::now();
+        for (net, (_, inputs)) in compiled.iter().zip(&brains) {
+            let mut state = BrainState::default();
+            for input in inputs {
+                std::hint::black_box(net.step(input, &mut state, &mut sums));
+            }
+        }
+        let compiled = start.elapsed();
+
+        println!(
+            "10000 brains, 5 steps: step {:?}, compiled {:?} ({:.1}x)",
+            interpreted,
+            compiled,
+            interpreted.as_secs_f64() / compiled.as_secs_f64()
+        );
+        assert!(compiled < interpreted);
+    }
 }
@@ -0,0 +1,253 @@
+use crate::utils::Grid2d;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, path::PathBuf};
+
+//cells organisms can't enter, in grid coordinates. The map is stretched over the whole grid,
+//rectangles and lines are added on top of it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObstacleConfig {
+    //ascii (.txt, `#` marks an obstacle) or png (dark pixels are obstacles) map, relative to
+    //the config file
+    pub map: Option<PathBuf>,
+    pub rectangles: Vec<ObstacleRectangle>,
+    pub lines: Vec<ObstacleLine>,
+    //the map file contents, read when the config is loaded
+    #[serde(skip)]
+    pub loaded_map: Option<ObstacleMap>,
+}
+
+//every cell from min to max (inclusive)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObstacleRectangle {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+//every cell on the line from `from` to `to`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObstacleLine {
+    pub from: Vec2,
+    pub to: Vec2,
+}
+
+//obstacle cells of a map file, row 0 is the top of the map
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObstacleMap {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<bool>,
+}
+
+impl ObstacleMap {
+    pub fn load(path: &Path) -> Result<ObstacleMap, String> {
+        let is_png = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        if is_png {
+            let image = image::open(path)
+                .map_err(|error| error.to_string())?
+                .to_luma8();
+            return Ok(ObstacleMap {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                cells: image.pixels().map(|pixel| pixel.0[0] < 128).collect(),
+            });
+        }
+
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        ObstacleMap::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<ObstacleMap, String> {
+        let rows: Vec<&str> = text.lines().filter(|row| !row.is_empty()).collect();
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        if width == 0 {
+            return Err(String::from("the map is empty"));
+        }
+
+        let mut cells = vec![false; width * rows.len()];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                cells[y * width + x] = cell == '#';
+            }
+        }
+        Ok(ObstacleMap {
+            width,
+            height: rows.len(),
+            cells,
+        })
+    }
+}
+
+//cells of a line, Bresenham's algorithm
+fn line_cells(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut error = dx + dy;
+    let mut cells = vec![(x, y)];
+    while (x, y) != to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+        cells.push((x, y));
+    }
+    cells
+}
+
+impl ObstacleConfig {
+    pub fn grid(&self, width: usize, height: usize) -> Grid2d<bool> {
+        let mut grid = Grid2d::new(width, height);
+        let mut set = |x: i32, y: i32| {
+            if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                grid.set(x as usize, y as usize, true);
+            }
+        };
+
+        if let Some(map) = &self.loaded_map {
+            for y in 0..height {
+                for x in 0..width {
+                    let map_x = x * map.width / width;
+                    let map_y = (height - 1 - y) * map.height / height;
+                    if map.cells[map_y * map.width + map_x] {
+                        set(x as i32, y as i32);
+                    }
+                }
+            }
+        }
+
+        for rectangle in &self.rectangles {
+            for y in rectangle.min.y as i32..=rectangle.max.y as i32 {
+                for x in rectangle.min.x as i32..=rectangle.max.x as i32 {
+                    set(x, y);
+                }
+            }
+        }
+
+        for line in &self.lines {
+            let from = (line.from.x as i32, line.from.y as i32);
+            let to = (line.to.x as i32, line.to.y as i32);
+            for (x, y) in line_cells(from, to) {
+                set(x, y);
+            }
+        }
+
+        grid
+    }
+}
+
+//horizontal runs of obstacle cells as (row, first column, last column), used for drawing
+pub fn obstacle_runs(obstacles: &Grid2d<bool>) -> Vec<(usize, usize, usize)> {
+    let mut runs = vec![];
+    for y in 0..obstacles.height {
+        let mut start = None;
+        for x in 0..=obstacles.width {
+            let blocked = x < obstacles.width && obstacles.get(x, y).is_some();
+            match (blocked, start) {
+                (true, None) => start = Some(x),
+                (false, Some(first)) => {
+                    runs.push((y, first, x - 1));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    runs
+}
+
+mod tests {
+    use super::*;
+
+    fn cells(grid: &Grid2d<bool>) -> Vec<(usize, usize)> {
+        let mut cells = vec![];
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                if grid.get(x, y).is_some() {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn rectangles_and_lines() {
+        let config = ObstacleConfig {
+            rectangles: vec![ObstacleRectangle {
+                min: Vec2::new(1.0, 1.0),
+                max: Vec2::new(2.0, 2.0),
+            }],
+            lines: vec![ObstacleLine {
+                from: Vec2::new(0.0, 5.0),
+                to: Vec2::new(3.0, 8.0),
+            }],
+            ..ObstacleConfig::default()
+        };
+        let grid = config.grid(10, 10);
+        assert!(
+            cells(&grid)
+                == vec![
+                    (1, 1),
+                    (2, 1),
+                    (1, 2),
+                    (2, 2),
+                    (0, 5),
+                    (1, 6),
+                    (2, 7),
+                    (3, 8)
+                ]
+        );
+        assert!(
+            obstacle_runs(&grid)
+                == vec![
+                    (1, 1, 2),
+                    (2, 1, 2),
+                    (5, 0, 0),
+                    (6, 1, 1),
+                    (7, 2, 2),
+                    (8, 3, 3)
+                ]
+        );
+    }
+
+    #[test]
+    fn ascii_map() {
+        let map = ObstacleMap::parse("#..\n...\n..#\n").unwrap();
+        assert!(map.width == 3 && map.height == 3);
+        assert!(ObstacleMap::parse("\n\n").is_err());
+
+        //stretched over a 6 x 6 grid, the first row is the top of the grid
+        let config = ObstacleConfig {
+            loaded_map: Some(map),
+            ..ObstacleConfig::default()
+        };
+        assert!(
+            cells(&config.grid(6, 6))
+                == vec![
+                    (4, 0),
+                    (5, 0),
+                    (4, 1),
+                    (5, 1),
+                    (0, 4),
+                    (1, 4),
+                    (0, 5),
+                    (1, 5)
+                ]
+        );
+    }
+}
@@ -1,11 +1,15 @@
 //organisms functionality
 
-use crate::{gene::Genome, neural::NeuralNet, utils::bernoulli_trial};
+use crate::{
+    gene::{Genome, GenomeError},
+    neural::{BrainState, CompiledNet, NeuralNet},
+    sensors::SenseContext,
+};
 use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
-
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     West,
     East,
@@ -17,99 +21,166 @@ pub enum Direction {
     NorthEast,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Action {
     Move(Direction),
     Nothing,
+    //leave pheromone on the current cell
+    EmitPheromone,
+    //kill the organism in the neighbouring cell in this direction
+    Kill(Direction),
+    //rotate the heading by eighths of a turn clockwise (negative for counter clockwise)
+    Turn(i32),
+    //change the period (in steps) of the organism's oscillator sensor
+    SetOscillatorPeriod(f32),
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Organism {
     pub brain: NeuralNet,
     pub genome: Genome,
     pub position: Vec2,
+    //facing direction, changes with every move and turn
+    pub heading: Direction,
+    //direction of the last successful move, None before the first move
+    pub last_move: Option<Direction>,
+    //period of the oscillator sensor set by the organism, the configured period if None
+    pub oscillator_period: Option<f32>,
+    //spent by living and moving, see EnergyConfig
+    pub energy: f32,
+    //assigned at the start of every generation when species are tracked, see species.rs
+    pub species: Option<usize>,
+    //activations of the brain after the last poll, read by recurrent connections
+    pub memory: BrainState,
 }
 
-fn resolve_directions(directions: Vec<Direction>) -> Option<Direction> {
-    // Takes a list of directions and returns the final direction
-    if directions.len() == 1 {
-        return Some(directions[0]);
+//the brain of the organism compiled for stepping, added when the organism is spawned
+#[derive(Component, Clone, Debug, Default)]
+pub struct CompiledBrain(pub CompiledNet);
+
+//reused from one poll to the next so polling doesn't allocate, `actions` and `memory` hold
+//the result of the last poll
+#[derive(Default)]
+pub struct PollBuffers {
+    pub inputs: Vec<f32>,
+    pub sums: Vec<f32>,
+    pub moves: Vec<Direction>,
+    pub actions: Vec<Action>,
+    pub memory: BrainState,
+}
+
+impl Direction {
+    //clockwise, starting north
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    //the direction `steps` eighths of a turn clockwise (negative for counter clockwise)
+    pub fn rotate(&self, steps: i32) -> Direction {
+        let index = Direction::ALL.iter().position(|d| d == self).unwrap() as i32;
+        Direction::ALL[(index + steps).rem_euclid(8) as usize]
     }
 
-    let (mut dir_x, mut dir_y) = (0, 0);
+    pub fn random(rng: &mut impl Rng) -> Direction {
+        Direction::ALL[rng.gen_range(0..Direction::ALL.len())]
+    }
 
-    // Accumulate direction components
-    for direction in directions.iter() {
-        match direction {
-            Direction::West => dir_x -= 1,
-            Direction::East => dir_x += 1,
-            Direction::North => dir_y += 1,
-            Direction::South => dir_y -= 1,
-            _ => {}
-        }
+    //clockwise angle from north in radians
+    pub fn angle(&self) -> f32 {
+        let index = Direction::ALL.iter().position(|d| d == self).unwrap();
+        index as f32 * std::f32::consts::FRAC_PI_4
     }
 
-    match (dir_x, dir_y) {
-        (0, 0) => None,
-        (0, y) if y > 0 => Some(Direction::North),
-        (0, _) => Some(Direction::South),
-        (x, 0) if x > 0 => Some(Direction::East),
-        (_, 0) => Some(Direction::West),
-        (x, y) if x > 0 && y > 0 => Some(Direction::NorthEast),
-        (x, y) if x > 0 && y < 0 => Some(Direction::SouthEast),
-        (x, y) if x < 0 && y > 0 => Some(Direction::NorthWest),
-        _ => Some(Direction::SouthWest),
+    //direction of a unit offset, None for (0, 0)
+    pub fn from_offset(offset: (i32, i32)) -> Option<Direction> {
+        Direction::ALL.into_iter().find(|d| d.offset() == offset)
     }
-}
 
-//input structure
-//0 - Px - x position of the organism
-//1 - Py - y position of the organism
+    //change in grid position when moving in this direction
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::West => (-1, 0),
+            Direction::East => (1, 0),
+            Direction::North => (0, 1),
+            Direction::South => (0, -1),
+            Direction::SouthWest => (-1, -1),
+            Direction::SouthEast => (1, -1),
+            Direction::NorthWest => (-1, 1),
+            Direction::NorthEast => (1, 1),
+        }
+    }
+}
 
-//output structure
-//0 - Mx - move in x direction
-//1 - My - move in y direction
+fn resolve_directions(directions: &[Direction]) -> Option<Direction> {
+    // Takes a list of directions and returns the final direction
+    if directions.len() == 1 {
+        return Some(directions[0]);
+    }
 
-impl Organism {
-    pub fn poll(&self, input: Vec<f32>) -> Option<Direction> {
-        let output = self.brain.forward(input);
-        let mx = output[0];
-        let my = output[1];
+    // Accumulate direction components
+    let (dir_x, dir_y) = directions.iter().fold((0, 0), |(x, y), direction| {
+        let (dx, dy) = direction.offset();
+        (x + dx, y + dy)
+    });
 
-        let mut move_directions: Vec<Direction> = vec![];
+    Direction::from_offset((dir_x.signum(), dir_y.signum()))
+}
 
-        if mx > 0.0 && bernoulli_trial(mx) {
-            move_directions.push(Direction::East)
-        } else if mx < 0.0 && bernoulli_trial(-mx) {
-            move_directions.push(Direction::West)
-        }
+//input structure: the sensors enabled in the config, see sensors.rs
 
-        if my > 0.0 && bernoulli_trial(my) {
-            move_directions.push(Direction::North)
-        } else if my < 0.0 && bernoulli_trial(-my) {
-            move_directions.push(Direction::South)
-        }
+//output structure: the action neurons enabled in the config, see actions.rs
 
-        resolve_directions(move_directions)
+impl Organism {
+    //builds an organism whose brain is decoded from the given genome
+    pub fn from_genome(genome: Genome, position: Vec2) -> Result<Self, GenomeError> {
+        let brain = NeuralNet::try_from(&genome)?;
+        Ok(Organism {
+            brain,
+            genome,
+            position,
+            heading: Direction::North,
+            last_move: None,
+            oscillator_period: None,
+            energy: 0.0,
+            species: None,
+            memory: BrainState::default(),
+        })
     }
-}
-
-pub struct OrganismUpdateStore {
-    pub updates: HashMap<(i32, i32), (u32, Action)>,
-}
 
-impl OrganismUpdateStore {
-    pub fn new() -> Self {
-        OrganismUpdateStore {
-            updates: HashMap::new(),
+    //runs the compiled brain on the sensor inputs, returns the combined move direction. Every
+    //other action the organism takes this step is left in buffers.actions and the memory to
+    //keep for the next step in buffers.memory
+    pub fn poll(
+        &self,
+        brain: &CompiledNet,
+        context: &SenseContext,
+        buffers: &mut PollBuffers,
+        rng: &mut impl Rng,
+    ) -> Option<Direction> {
+        buffers
+            .memory
+            .activations
+            .clone_from(&self.memory.activations);
+        context.sense(self, rng, &mut buffers.inputs);
+        let output = brain.step(&buffers.inputs, &mut buffers.memory, &mut buffers.sums);
+
+        buffers.moves.clear();
+        buffers.actions.clear();
+        for (neuron, value) in context.generation.brain.actions.iter().zip(output) {
+            match neuron.act(*value, self, context, rng) {
+                Some(Action::Move(direction)) => buffers.moves.push(direction),
+                Some(action) => buffers.actions.push(action),
+                None => {}
+            }
         }
-    }
-
-    pub fn add_organism(&mut self, id: u32, position: (i32, i32), action: Action) {
-        self.updates.insert(position, (id, action));
-    }
 
-    pub fn get(&self, position: (i32, i32)) -> Option<&(u32, Action)> {
-        self.updates.get(&position)
+        resolve_directions(&buffers.moves)
     }
 }
@@ -0,0 +1,335 @@
+use crate::{environment::Environment, organism::Organism};
+use bevy::prelude::*;
+use std::ops::Range;
+
+//areas of the environment (in grid coordinates) where organisms are safe, used for drawing
+#[derive(Clone, Copy, Debug)]
+pub enum Zone {
+    Rectangle { min: Vec2, max: Vec2 },
+    Circle { center: Vec2, radius: f32 },
+}
+
+//zone covering the cells in the given ranges, None if they are empty
+fn cells(x: Range<usize>, y: Range<usize>) -> Option<Zone> {
+    (!x.is_empty() && !y.is_empty()).then(|| Zone::Rectangle {
+        min: Vec2::new(x.start as f32, y.start as f32),
+        max: Vec2::new((x.end - 1) as f32, (y.end - 1) as f32),
+    })
+}
+
+//the cells within `extent` of the start and the end of a row or column of `length` cells, the
+//end range starts after the start range so they never overlap
+fn edges(extent: usize, length: usize) -> (Range<usize>, Range<usize>) {
+    let start = extent.min(length);
+    (0..start, length.saturating_sub(extent).max(start)..length)
+}
+
+//decides whether an organism survives to reproduce at the end of a generation
+pub trait SelectionCriterion: Send + Sync {
+    fn survives(
+        &self,
+        organism: &Organism,
+        position: (usize, usize),
+        environment: &Environment,
+    ) -> bool;
+
+    fn zones(&self, _environment: &Environment) -> Vec<Zone> {
+        vec![]
+    }
+}
+
+pub struct EastHalf;
+
+impl SelectionCriterion for EastHalf {
+    fn survives(&self, _: &Organism, position: (usize, usize), environment: &Environment) -> bool {
+        position.0 >= environment.organisms.width / 2
+    }
+
+    fn zones(&self, environment: &Environment) -> Vec<Zone> {
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        vec![Zone::Rectangle {
+            min: Vec2::new((width / 2) as f32, 0.0),
+            max: Vec2::new((width - 1) as f32, (height - 1) as f32),
+        }]
+    }
+}
+
+//every organism that is still alive survives, for worlds where organisms can starve or be
+//killed
+pub struct Alive;
+
+impl SelectionCriterion for Alive {
+    fn survives(&self, _: &Organism, _: (usize, usize), _: &Environment) -> bool {
+        true
+    }
+}
+
+//survive within `radius` cells of `center`
+pub struct InsideCircle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl SelectionCriterion for InsideCircle {
+    fn survives(&self, _: &Organism, position: (usize, usize), _: &Environment) -> bool {
+        Vec2::new(position.0 as f32, position.1 as f32).distance(self.center) <= self.radius
+    }
+
+    fn zones(&self, _: &Environment) -> Vec<Zone> {
+        vec![Zone::Circle {
+            center: self.center,
+            radius: self.radius,
+        }]
+    }
+}
+
+//survive within the cells from `min` to `max` (inclusive)
+pub struct InsideRectangle {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl SelectionCriterion for InsideRectangle {
+    fn survives(&self, _: &Organism, position: (usize, usize), _: &Environment) -> bool {
+        let (x, y) = (position.0 as f32, position.1 as f32);
+        x >= self.min.x && x <= self.max.x && y >= self.min.y && y <= self.max.y
+    }
+
+    //clipped to the grid
+    fn zones(&self, environment: &Environment) -> Vec<Zone> {
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        let start = |min: f32| min.max(0.0).ceil() as usize;
+        let end = |max: f32, length: usize| ((max.floor() + 1.0).max(0.0) as usize).min(length);
+        cells(
+            start(self.min.x)..end(self.max.x, width),
+            start(self.min.y)..end(self.max.y, height),
+        )
+        .into_iter()
+        .collect()
+    }
+}
+
+//survive within `distance` cells of any wall
+pub struct NearWalls {
+    pub distance: usize,
+}
+
+impl SelectionCriterion for NearWalls {
+    fn survives(&self, _: &Organism, position: (usize, usize), environment: &Environment) -> bool {
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        position.0 < self.distance
+            || position.1 < self.distance
+            || position.0 >= width.saturating_sub(self.distance)
+            || position.1 >= height.saturating_sub(self.distance)
+    }
+
+    fn zones(&self, environment: &Environment) -> Vec<Zone> {
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        let (west, east) = edges(self.distance, width);
+        let (south, north) = edges(self.distance, height);
+        //the west and east strips span the full height, the others fill the gap between them
+        [
+            cells(west.clone(), 0..height),
+            cells(east.clone(), 0..height),
+            cells(west.end..east.start, south),
+            cells(west.end..east.start, north),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+//survive within a `size` x `size` square in any corner
+pub struct InCorners {
+    pub size: usize,
+}
+
+impl SelectionCriterion for InCorners {
+    fn survives(&self, _: &Organism, position: (usize, usize), environment: &Environment) -> bool {
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        let near_x = position.0 < self.size || position.0 >= width.saturating_sub(self.size);
+        let near_y = position.1 < self.size || position.1 >= height.saturating_sub(self.size);
+        near_x && near_y
+    }
+
+    fn zones(&self, environment: &Environment) -> Vec<Zone> {
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        let (west, east) = edges(self.size, width);
+        let (south, north) = edges(self.size, height);
+        [
+            cells(west.clone(), south.clone()),
+            cells(east.clone(), south),
+            cells(west, north.clone()),
+            cells(east, north),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+pub struct Custom {
+    predicate: Box<dyn Fn(&Organism, (usize, usize), &Environment) -> bool + Send + Sync>,
+}
+
+impl Custom {
+    pub fn new(
+        predicate: impl Fn(&Organism, (usize, usize), &Environment) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Custom {
+            predicate: Box::new(predicate),
+        }
+    }
+}
+
+impl SelectionCriterion for Custom {
+    fn survives(
+        &self,
+        organism: &Organism,
+        position: (usize, usize),
+        environment: &Environment,
+    ) -> bool {
+        (self.predicate)(organism, position, environment)
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::{
+        neural::{BrainState, NeuralNet},
+        organism::Direction,
+    };
+    use bevy::color::palettes::css::BLACK;
+
+    fn test_environment() -> Environment {
+        // 20 x 10 grid
+        Environment::new(100.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0)
+    }
+
+    fn test_organism() -> Organism {
+        let brain = NeuralNet::new(vec![2, 2]);
+        Organism {
+            genome: brain.clone().into(),
+            brain,
+            position: Vec2::ZERO,
+            heading: Direction::North,
+            last_move: None,
+            oscillator_period: None,
+            energy: 0.0,
+            species: None,
+            memory: BrainState::default(),
+        }
+    }
+
+    fn survivors(criterion: &dyn SelectionCriterion) -> Vec<(usize, usize)> {
+        survivors_in(criterion, &test_environment())
+    }
+
+    fn survivors_in(
+        criterion: &dyn SelectionCriterion,
+        environment: &Environment,
+    ) -> Vec<(usize, usize)> {
+        let organism = test_organism();
+        let mut survivors = vec![];
+        for y in 0..environment.organisms.height {
+            for x in 0..environment.organisms.width {
+                if criterion.survives(&organism, (x, y), environment) {
+                    survivors.push((x, y));
+                }
+            }
+        }
+        survivors
+    }
+
+    fn zone_cells(criterion: &dyn SelectionCriterion) -> usize {
+        criterion
+            .zones(&test_environment())
+            .iter()
+            .map(|zone| match zone {
+                Zone::Rectangle { min, max } => {
+                    ((max.x - min.x + 1.0) * (max.y - min.y + 1.0)) as usize
+                }
+                Zone::Circle { .. } => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn east_half() {
+        let survivors = survivors(&EastHalf);
+        assert!(survivors.len() == 100);
+        assert!(survivors.iter().all(|(x, _)| *x >= 10));
+        assert!(zone_cells(&EastHalf) == 100);
+    }
+
+    #[test]
+    fn inside_shapes() {
+        let circle = InsideCircle {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+        };
+        assert!(survivors(&circle) == vec![(5, 4), (4, 5), (5, 5), (6, 5), (5, 6)]);
+
+        let rectangle = InsideRectangle {
+            min: Vec2::new(2.0, 3.0),
+            max: Vec2::new(4.0, 3.0),
+        };
+        assert!(survivors(&rectangle) == vec![(2, 3), (3, 3), (4, 3)]);
+        assert!(zone_cells(&rectangle) == 3);
+    }
+
+    #[test]
+    fn walls_and_corners() {
+        let walls = NearWalls { distance: 2 };
+        // everything but the inner 16 x 6 cells
+        assert!(survivors(&walls).len() == 200 - 96);
+        assert!(zone_cells(&walls) == 200 - 96);
+
+        let corners = InCorners { size: 2 };
+        assert!(survivors(&corners).len() == 16);
+        assert!(survivors(&corners).contains(&(19, 9)));
+        assert!(zone_cells(&corners) == 16);
+    }
+
+    #[test]
+    fn zones_on_a_small_grid() {
+        // 5 x 3 grid, too small for some of the criteria
+        let environment = Environment::new(25.0, 15.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        let criteria: [&dyn SelectionCriterion; 6] = [
+            &NearWalls { distance: 1 },
+            &NearWalls { distance: 2 },
+            &InCorners { size: 1 },
+            &InCorners { size: 2 },
+            &InCorners { size: 4 },
+            &InsideRectangle {
+                min: Vec2::new(-2.0, 1.0),
+                max: Vec2::new(9.0, 9.0),
+            },
+        ];
+        for criterion in criteria {
+            //every cell covered by the zones, in the order survivors_in visits them
+            let mut covered = vec![];
+            for zone in criterion.zones(&environment) {
+                let Zone::Rectangle { min, max } = zone else {
+                    panic!("expected a rectangle");
+                };
+                assert!(min.x >= 0.0 && min.y >= 0.0 && max.x <= 4.0 && max.y <= 2.0);
+                for y in min.y as usize..=max.y as usize {
+                    for x in min.x as usize..=max.x as usize {
+                        covered.push((x, y));
+                    }
+                }
+            }
+            covered.sort_by_key(|(x, y)| (*y, *x));
+            //no cell is covered twice and the zones match the survivors
+            assert!(covered == survivors_in(criterion, &environment));
+        }
+    }
+
+    #[test]
+    fn custom_criterion() {
+        let diagonal = Custom::new(|_, (x, y), _| x == y);
+        assert!(survivors(&diagonal).len() == 10);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{environment::Environment, generation::Generation, organism::Organism};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+//inputs of the brain, the enabled sensors (in config order) make up the input layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sensor {
+    LocationX,
+    LocationY,
+    WallDistanceX,
+    WallDistanceY,
+    Density,
+    BlockedForward,
+    BlockedLeft,
+    BlockedRight,
+    Age,
+    Oscillator,
+    Random,
+    LastMoveX,
+    LastMoveY,
+    HeadingX,
+    HeadingY,
+    GeneticSimilarity,
+    Pheromone,
+    Energy,
+    Food,
+}
+
+impl Sensor {
+    pub const ALL: [Sensor; 19] = [
+        Sensor::LocationX,
+        Sensor::LocationY,
+        Sensor::WallDistanceX,
+        Sensor::WallDistanceY,
+        Sensor::Density,
+        Sensor::BlockedForward,
+        Sensor::BlockedLeft,
+        Sensor::BlockedRight,
+        Sensor::Age,
+        Sensor::Oscillator,
+        Sensor::Random,
+        Sensor::LastMoveX,
+        Sensor::LastMoveY,
+        Sensor::HeadingX,
+        Sensor::HeadingY,
+        Sensor::GeneticSimilarity,
+        Sensor::Pheromone,
+        Sensor::Energy,
+        Sensor::Food,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Sensor::LocationX => "Lx",
+            Sensor::LocationY => "Ly",
+            Sensor::WallDistanceX => "Wx",
+            Sensor::WallDistanceY => "Wy",
+            Sensor::Density => "Dn",
+            Sensor::BlockedForward => "Bf",
+            Sensor::BlockedLeft => "Bl",
+            Sensor::BlockedRight => "Br",
+            Sensor::Age => "Ag",
+            Sensor::Oscillator => "Os",
+            Sensor::Random => "Rn",
+            Sensor::LastMoveX => "LMx",
+            Sensor::LastMoveY => "LMy",
+            Sensor::HeadingX => "Hx",
+            Sensor::HeadingY => "Hy",
+            Sensor::GeneticSimilarity => "Gs",
+            Sensor::Pheromone => "Ph",
+            Sensor::Energy => "En",
+            Sensor::Food => "Fd",
+        }
+    }
+
+    //name used in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sensor::LocationX => "location_x",
+            Sensor::LocationY => "location_y",
+            Sensor::WallDistanceX => "wall_distance_x",
+            Sensor::WallDistanceY => "wall_distance_y",
+            Sensor::Density => "density",
+            Sensor::BlockedForward => "blocked_forward",
+            Sensor::BlockedLeft => "blocked_left",
+            Sensor::BlockedRight => "blocked_right",
+            Sensor::Age => "age",
+            Sensor::Oscillator => "oscillator",
+            Sensor::Random => "random",
+            Sensor::LastMoveX => "last_move_x",
+            Sensor::LastMoveY => "last_move_y",
+            Sensor::HeadingX => "heading_x",
+            Sensor::HeadingY => "heading_y",
+            Sensor::GeneticSimilarity => "genetic_similarity",
+            Sensor::Pheromone => "pheromone",
+            Sensor::Energy => "energy",
+            Sensor::Food => "food",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Sensor::LocationX => "East West location",
+            Sensor::LocationY => "North South location",
+            Sensor::WallDistanceX => "Distance to the nearest east or west wall",
+            Sensor::WallDistanceY => "Distance to the nearest north or south wall",
+            Sensor::Density => "Share of occupied cells in the neighbourhood",
+            Sensor::BlockedForward => "Cell ahead is a wall or occupied",
+            Sensor::BlockedLeft => "Cell 90 degrees left of the heading is a wall or occupied",
+            Sensor::BlockedRight => "Cell 90 degrees right of the heading is a wall or occupied",
+            Sensor::Age => "Steps into the generation",
+            Sensor::Oscillator => "Sine wave with a period of oscillator_period steps (or the period set by set_oscillator)",
+            Sensor::Random => "Random noise",
+            Sensor::LastMoveX => "East West component of the last move",
+            Sensor::LastMoveY => "North South component of the last move",
+            Sensor::HeadingX => "East West component of the heading",
+            Sensor::HeadingY => "North South component of the heading",
+            Sensor::GeneticSimilarity => "Mean genome similarity to the neighbours",
+            Sensor::Pheromone => "Strongest pheromone in the neighbourhood",
+            Sensor::Energy => "Energy as a share of the maximum energy",
+            Sensor::Food => "Share of the cells in the neighbourhood holding food",
+        }
+    }
+
+    pub fn range(&self) -> &'static str {
+        match self {
+            Sensor::Oscillator
+            | Sensor::LastMoveX
+            | Sensor::LastMoveY
+            | Sensor::HeadingX
+            | Sensor::HeadingY => "-1 1",
+            Sensor::BlockedForward | Sensor::BlockedLeft | Sensor::BlockedRight => "0 or 1",
+            _ => "0 1",
+        }
+    }
+
+    pub fn sense(&self, organism: &Organism, context: &SenseContext, rng: &mut impl Rng) -> f32 {
+        let environment = context.environment;
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        let position = (organism.position.x as usize, organism.position.y as usize);
+
+        match self {
+            Sensor::LocationX => organism.position.x / width as f32,
+            Sensor::LocationY => organism.position.y / height as f32,
+            //a wrapped axis has no walls, as far away as a wall can be
+            Sensor::WallDistanceX if environment.topology.wraps_x() => 1.0,
+            Sensor::WallDistanceY if environment.topology.wraps_y() => 1.0,
+            Sensor::WallDistanceX => wall_distance(position.0, width),
+            Sensor::WallDistanceY => wall_distance(position.1, height),
+            Sensor::Density => {
+                let radius = context.generation.brain.neighbourhood_radius;
+                let cells = environment.neighbourhood(position, radius).len();
+                let neighbours = environment.neighbours(position, radius).len();
+                neighbours as f32 / (cells - 1).max(1) as f32
+            }
+            Sensor::BlockedForward | Sensor::BlockedLeft | Sensor::BlockedRight => {
+                let direction = match self {
+                    Sensor::BlockedLeft => organism.heading.rotate(-2),
+                    Sensor::BlockedRight => organism.heading.rotate(2),
+                    _ => organism.heading,
+                };
+                if environment.blocked(position, direction) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Sensor::Age => {
+                context.generation.step as f32 / context.generation.steps_per_generation as f32
+            }
+            Sensor::Oscillator => {
+                let period = organism
+                    .oscillator_period
+                    .unwrap_or(context.generation.brain.oscillator_period as f32);
+                (std::f32::consts::TAU * context.generation.step as f32 / period).sin()
+            }
+            Sensor::Random => rng.gen_range(0.0..1.0),
+            Sensor::LastMoveX => organism.last_move.map_or(0.0, |d| d.offset().0 as f32),
+            Sensor::LastMoveY => organism.last_move.map_or(0.0, |d| d.offset().1 as f32),
+            Sensor::HeadingX => organism.heading.offset().0 as f32,
+            Sensor::HeadingY => organism.heading.offset().1 as f32,
+            Sensor::GeneticSimilarity => {
+                let radius = context.generation.brain.neighbourhood_radius;
+                let similarities: Vec<f32> = environment
+                    .neighbours(position, radius)
+                    .iter()
+                    .filter_map(|(_, id)| context.organisms.get(id))
+                    .map(|neighbour| organism.genome.similarity(&neighbour.genome))
+                    .collect();
+                if similarities.is_empty() {
+                    0.0
+                } else {
+                    similarities.iter().sum::<f32>() / similarities.len() as f32
+                }
+            }
+            Sensor::Pheromone => {
+                let radius = context.generation.brain.neighbourhood_radius;
+                let pheromones = &environment.pheromones;
+                environment
+                    .neighbourhood(position, radius)
+                    .into_iter()
+                    .map(|(x, y)| pheromones.get(x, y).copied().unwrap_or(0.0))
+                    .fold(0.0, f32::max)
+            }
+            Sensor::Energy => organism.energy / context.generation.energy.max,
+            Sensor::Food => {
+                let radius = context.generation.brain.neighbourhood_radius;
+                let cells = environment.neighbourhood(position, radius);
+                let food = cells
+                    .iter()
+                    .filter(|(x, y)| environment.food.get(*x, *y).is_some())
+                    .count();
+                food as f32 / cells.len() as f32
+            }
+        }
+    }
+}
+
+//0 against a wall, 1 in the middle
+fn wall_distance(position: usize, size: usize) -> f32 {
+    if size <= 2 {
+        return 0.0;
+    }
+    let distance = position.min(size - 1 - position);
+    distance as f32 / ((size - 1) / 2) as f32
+}
+
+//everything the sensors read besides the organism itself
+pub struct SenseContext<'a> {
+    pub environment: &'a Environment,
+    //every organism by entity bits
+    pub organisms: &'a HashMap<u64, &'a Organism>,
+    pub generation: &'a Generation,
+}
+
+impl SenseContext<'_> {
+    //fills `inputs` with the input layer values of the organism's brain
+    pub fn sense(&self, organism: &Organism, rng: &mut impl Rng, inputs: &mut Vec<f32>) {
+        inputs.clear();
+        inputs.extend(
+            self.generation
+                .brain
+                .sensors
+                .iter()
+                .map(|sensor| sensor.sense(organism, self, rng)),
+        );
+    }
+}
+
+//markdown table of every sensor, kept in the README
+pub fn sensor_table() -> String {
+    let mut table =
+        String::from("| Code | Config name | Description | Range |\n|---|---|---|---|\n");
+    for sensor in Sensor::ALL {
+        table += &format!(
+            "| {} | `{}` | {} | {} |\n",
+            sensor.code(),
+            sensor.name(),
+            sensor.description(),
+            sensor.range()
+        );
+    }
+    table
+}
+
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        neural::{BrainState, NeuralNet},
+        organism::Direction,
+    };
+    use bevy::{color::palettes::css::BLACK, math::Vec2, prelude::Color};
+
+    fn organism_at(x: f32, y: f32) -> Organism {
+        let brain = NeuralNet::new(vec![2, 2]);
+        Organism {
+            genome: brain.clone().into(),
+            brain,
+            position: Vec2::new(x, y),
+            heading: Direction::North,
+            last_move: None,
+            oscillator_period: None,
+            energy: 50.0,
+            species: None,
+            memory: BrainState::default(),
+        }
+    }
+
+    fn generation(step: usize) -> Generation {
+        let config = Config::default();
+        let mut generation = Generation::new(
+            100,
+            10,
+            config.selection.criterion(),
+            config.mutation,
+            config.generation.reproduction,
+            config.brain,
+            config.energy,
+        );
+        generation.step = step;
+        generation
+    }
+
+    #[test]
+    fn config_names_match() {
+        for sensor in Sensor::ALL {
+            let parsed: Sensor =
+                serde::Deserialize::deserialize(toml::Value::String(sensor.name().into())).unwrap();
+            assert!(parsed == sensor);
+        }
+    }
+
+    #[test]
+    fn readme_lists_every_sensor() {
+        assert!(
+            include_str!("../README.md").contains(&sensor_table()),
+            "README gene table is out of date, regenerate it with --gene-table"
+        );
+    }
+
+    #[test]
+    fn sense_surroundings() {
+        // 10 x 10 grid
+        let mut environment =
+            Environment::new(50.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        let mut organism = organism_at(0.0, 4.0);
+        let neighbour = organism_at(1.0, 4.0);
+        environment.organisms.set(0, 4, 1);
+        environment.organisms.set(1, 4, 2);
+        environment.pheromones.set(2, 6, 0.5);
+        environment.pheromones.set(3, 6, 0.9);
+        environment.food.set(0, 2, true);
+        environment.food.set(2, 2, true);
+        environment.food.set(3, 3, true);
+        environment.obstacles.set(0, 3, true);
+        let polled = organism.clone();
+        let organisms = HashMap::from([(1, &polled), (2, &neighbour)]);
+        let generation = generation(25);
+        let context = SenseContext {
+            environment: &environment,
+            organisms: &organisms,
+            generation: &generation,
+        };
+        let rng = &mut rand::thread_rng();
+        let sense = |sensor: Sensor, organism: &Organism| {
+            sensor.sense(organism, &context, &mut rand::thread_rng())
+        };
+
+        assert!(sense(Sensor::LocationY, &organism) == 0.4);
+        assert!(sense(Sensor::WallDistanceX, &organism) == 0.0);
+        assert!(sense(Sensor::WallDistanceY, &organism) == 1.0);
+        // 1 of the 14 cells within 2 cells of a wall cell
+        assert!(sense(Sensor::Density, &organism) == 1.0 / 14.0);
+        assert!(sense(Sensor::Age, &organism) == 0.25);
+        assert!(sense(Sensor::Oscillator, &organism).abs() <= 1.0);
+        assert!(sense(Sensor::GeneticSimilarity, &organism) == 1.0);
+        assert!(sense(Sensor::Pheromone, &organism) == 0.5);
+        assert!(sense(Sensor::Energy, &organism) == 0.25);
+        assert!(sense(Sensor::Food, &organism) == 2.0 / 15.0);
+        assert!((0.0..1.0).contains(&Sensor::Random.sense(&organism, &context, rng)));
+
+        //facing north along the west wall with a neighbour to the east
+        assert!(sense(Sensor::BlockedForward, &organism) == 0.0);
+        assert!(sense(Sensor::BlockedLeft, &organism) == 1.0);
+        assert!(sense(Sensor::BlockedRight, &organism) == 1.0);
+        assert!(sense(Sensor::HeadingY, &organism) == 1.0);
+        organism.heading = Direction::East;
+        assert!(sense(Sensor::BlockedForward, &organism) == 1.0);
+        assert!(sense(Sensor::BlockedLeft, &organism) == 0.0);
+        //obstacle to the south
+        assert!(sense(Sensor::BlockedRight, &organism) == 1.0);
+        assert!(sense(Sensor::HeadingX, &organism) == 1.0);
+        assert!(sense(Sensor::HeadingY, &organism) == 0.0);
+
+        //the last move reads 0 before the first move and doesn't follow turns
+        assert!(sense(Sensor::LastMoveX, &organism) == 0.0);
+        assert!(sense(Sensor::LastMoveY, &organism) == 0.0);
+        organism.last_move = Some(Direction::SouthWest);
+        assert!(sense(Sensor::LastMoveX, &organism) == -1.0);
+        assert!(sense(Sensor::LastMoveY, &organism) == -1.0);
+        assert!(sense(Sensor::HeadingX, &organism) == 1.0);
+    }
+}
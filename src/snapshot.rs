@@ -0,0 +1,472 @@
+use crate::{
+    actions::ActionNeuron, config::BrainConfig, environment::Environment, generation::Generation,
+    organism::Organism, sensors::Sensor, species::assign_species, species::SpeciesTracker,
+    utils::Grid2d, utils::SimRng,
+};
+use bevy::{
+    ecs::system::{RunSystemOnce, SystemState},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+//snapshot files start with the magic bytes and the format version (little endian u32),
+//followed by the bincode encoded snapshot
+const SNAPSHOT_MAGIC: &[u8; 4] = b"EVOS";
+pub const SNAPSHOT_VERSION: u32 = 13;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Encoding(bincode::Error),
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    //the snapshot decoded but describes a world that can't exist
+    Invalid(String),
+    //the brains in the snapshot have different inputs, outputs or layers than the config
+    BrainMismatch {
+        snapshot: Box<BrainLayout>,
+        config: Box<BrainLayout>,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::Encoding(error) => write!(f, "corrupt snapshot: {}", error),
+            SnapshotError::NotASnapshot => write!(f, "not a snapshot file"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {} is not supported (expected {})",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Invalid(reason) => write!(f, "invalid snapshot: {}", reason),
+            SnapshotError::BrainMismatch { snapshot, config } => write!(
+                f,
+                "the snapshot brains have {} but the config has {}",
+                snapshot, config
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(error: bincode::Error) -> Self {
+        SnapshotError::Encoding(error)
+    }
+}
+
+//where snapshots are saved to and loaded from while running
+#[derive(Resource)]
+pub struct SnapshotPath(pub PathBuf);
+
+//snapshot to resume from on startup
+#[derive(Resource)]
+pub struct PendingSnapshot(pub Snapshot);
+
+//what the brains of the organisms in a snapshot are built for, a snapshot can only be run with
+//a config that has the same layout
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BrainLayout {
+    pub sensors: Vec<Sensor>,
+    pub actions: Vec<ActionNeuron>,
+    pub hidden_layers: Vec<usize>,
+}
+
+impl From<&BrainConfig> for BrainLayout {
+    fn from(brain: &BrainConfig) -> Self {
+        BrainLayout {
+            sensors: brain.sensors.clone(),
+            actions: brain.actions.clone(),
+            hidden_layers: brain.hidden_layers.clone(),
+        }
+    }
+}
+
+impl BrainLayout {
+    //neurons in each layer, see BrainConfig::definition
+    pub fn definition(&self) -> Vec<usize> {
+        let mut definition = vec![self.sensors.len()];
+        definition.extend(&self.hidden_layers);
+        definition.push(self.actions.len());
+        definition
+    }
+}
+
+impl fmt::Display for BrainLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sensors: Vec<&str> = self.sensors.iter().map(Sensor::name).collect();
+        let actions: Vec<&str> = self.actions.iter().map(ActionNeuron::name).collect();
+        write!(
+            f,
+            "sensors [{}], hidden layers {:?} and actions [{}]",
+            sensors.join(", "),
+            self.hidden_layers,
+            actions.join(", ")
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub environment: Environment,
+    pub organisms: Vec<Organism>,
+    pub generation: usize,
+    pub step: usize,
+    pub rng: SimRng,
+    pub species: SpeciesTracker,
+    pub brain: BrainLayout,
+}
+
+impl Snapshot {
+    pub fn capture(world: &mut World) -> Self {
+        let organisms = world.query::<&Organism>().iter(world).cloned().collect();
+        let generation = world.resource::<Generation>();
+        Snapshot {
+            environment: world.resource::<Environment>().clone(),
+            organisms,
+            generation: generation.count,
+            step: generation.step,
+            rng: world.resource::<SimRng>().clone(),
+            species: world.resource::<SpeciesTracker>().clone(),
+            brain: BrainLayout::from(&generation.brain),
+        }
+    }
+
+    //replaces the running simulation with the snapshot
+    pub fn restore(self, world: &mut World) {
+        let entities: Vec<Entity> = world
+            .query_filtered::<Entity, With<Organism>>()
+            .iter(world)
+            .collect();
+        for entity in entities {
+            world.despawn(entity);
+        }
+
+        let mut state: SystemState<(
+            Commands,
+            ResMut<Environment>,
+            ResMut<Generation>,
+            ResMut<SimRng>,
+            ResMut<SpeciesTracker>,
+        )> = SystemState::new(world);
+        let (mut commands, mut environment, mut generation, mut rng, mut species) =
+            state.get_mut(world);
+
+        let headless = environment.headless;
+        *environment = self.environment;
+        environment.headless = headless;
+        //the saved entity ids are stale, the grid is rebuilt as organisms are respawned
+        environment.organisms =
+            Grid2d::new(environment.organisms.width, environment.organisms.height);
+        for organism in self.organisms {
+            let position = (organism.position.x as usize, organism.position.y as usize);
+            environment.spawn_organism(&mut commands, organism, position);
+        }
+
+        generation.count = self.generation;
+        generation.step = self.step;
+        *rng = self.rng;
+        *species = self.species;
+
+        state.apply(world);
+        //organisms saved before they were sorted into species are sorted now, so they can be
+        //coloured by species as they are spawned
+        world.run_system_once(assign_species);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    //loads a snapshot to run with the given brain config
+    pub fn load(path: &Path, brain: &BrainConfig) -> Result<Self, SnapshotError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| SnapshotError::NotASnapshot)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let snapshot: Snapshot = bincode::deserialize_from(reader)?;
+        snapshot.validate(brain)?;
+        Ok(snapshot)
+    }
+
+    //checks that the snapshot can be restored and run with the given brain config
+    pub fn validate(&self, brain: &BrainConfig) -> Result<(), SnapshotError> {
+        let config = BrainLayout::from(brain);
+        if self.brain != config {
+            return Err(SnapshotError::BrainMismatch {
+                snapshot: Box::new(self.brain.clone()),
+                config: Box::new(config),
+            });
+        }
+
+        let invalid = |reason: String| Err(SnapshotError::Invalid(reason));
+        let environment = &self.environment;
+        let (width, height) = (environment.organisms.width, environment.organisms.height);
+        let grids = [
+            (
+                "organism",
+                environment.organisms.width,
+                environment.organisms.height,
+                environment.organisms.data.len(),
+            ),
+            (
+                "pheromone",
+                environment.pheromones.width,
+                environment.pheromones.height,
+                environment.pheromones.data.len(),
+            ),
+            (
+                "food",
+                environment.food.width,
+                environment.food.height,
+                environment.food.data.len(),
+            ),
+            (
+                "obstacle",
+                environment.obstacles.width,
+                environment.obstacles.height,
+                environment.obstacles.data.len(),
+            ),
+        ];
+        for (name, grid_width, grid_height, cells) in grids {
+            if (grid_width, grid_height) != (width, height) || cells != width * height {
+                return invalid(format!(
+                    "the {} grid doesn't match the {} x {} world",
+                    name, width, height
+                ));
+            }
+        }
+
+        let definition = self.brain.definition();
+        let mut occupied: Grid2d<bool> = Grid2d::new(width, height);
+        for organism in &self.organisms {
+            let (x, y) = (organism.position.x, organism.position.y);
+            let in_grid = x >= 0.0
+                && y >= 0.0
+                && x < width as f32
+                && y < height as f32
+                && x.fract() == 0.0
+                && y.fract() == 0.0;
+            if !in_grid {
+                return invalid(format!(
+                    "an organism at ({}, {}) is outside the {} x {} grid",
+                    x, y, width, height
+                ));
+            }
+            let (x, y) = (x as usize, y as usize);
+            if environment.obstacles.get(x, y) == Some(&true) {
+                return invalid(format!("an organism at ({}, {}) is on an obstacle", x, y));
+            }
+            if occupied.get(x, y) == Some(&true) {
+                return invalid(format!("two organisms share the cell ({}, {})", x, y));
+            }
+            occupied.set(x, y, true);
+
+            let brain = &organism.brain;
+            if brain.definition() != definition {
+                return invalid(format!(
+                    "an organism brain has layers {:?} instead of {:?}",
+                    brain.definition(),
+                    definition
+                ));
+            }
+            let mut connections_valid = true;
+            brain.for_each_neuron(|_, _, neuron| {
+                connections_valid &= neuron
+                    .connections
+                    .iter()
+                    .all(|connection| brain.is_valid_connection(connection.from, connection.to));
+            });
+            if !connections_valid {
+                return invalid(format!(
+                    "an organism at ({}, {}) has connections between missing neurons",
+                    x, y
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn save_snapshot(world: &mut World) {
+    let path = world.resource::<SnapshotPath>().0.clone();
+    match Snapshot::capture(world).save(&path) {
+        Ok(()) => info!("saved snapshot to {}", path.display()),
+        Err(error) => error!("failed to save snapshot to {}: {}", path.display(), error),
+    }
+}
+
+pub fn restore_pending_snapshot(world: &mut World) {
+    if let Some(PendingSnapshot(snapshot)) = world.remove_resource::<PendingSnapshot>() {
+        snapshot.restore(world);
+    }
+}
+
+//F5 saves a snapshot, F9 loads it back
+pub fn snapshot_keys(world: &mut World) {
+    let keys = world.resource::<ButtonInput<KeyCode>>();
+    let (save, load) = (
+        keys.just_pressed(KeyCode::F5),
+        keys.just_pressed(KeyCode::F9),
+    );
+
+    if save {
+        save_snapshot(world);
+    }
+
+    if load {
+        let path = world.resource::<SnapshotPath>().0.clone();
+        match Snapshot::load(&path, &world.resource::<Generation>().brain) {
+            Ok(snapshot) => {
+                snapshot.restore(world);
+                info!("loaded snapshot from {}", path.display());
+            }
+            Err(error) => error!("failed to load snapshot from {}: {}", path.display(), error),
+        }
+    }
+}
+
+pub fn save_snapshot_on_exit(world: &mut World) {
+    if !world.resource::<Events<AppExit>>().is_empty() {
+        save_snapshot(world);
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::neural::NeuralNet;
+    use bevy::color::palettes::css::BLACK;
+
+    fn test_snapshot() -> Snapshot {
+        // 10 x 10 grid
+        let environment = Environment::new(50.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        let brain = BrainConfig::default();
+        let mut rng = SimRng::new(1);
+        let organisms = [(1.0, 1.0), (2.0, 1.0)]
+            .into_iter()
+            .map(|(x, y)| {
+                Organism::from_genome(brain.random_brain(&mut rng).into(), Vec2::new(x, y)).unwrap()
+            })
+            .collect();
+        Snapshot {
+            environment,
+            organisms,
+            generation: 0,
+            step: 0,
+            rng,
+            species: SpeciesTracker::default(),
+            brain: BrainLayout::from(&brain),
+        }
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        let path = std::env::temp_dir().join("evo_reject_invalid_files.evo");
+
+        std::fs::write(&path, b"not a snapshot").unwrap();
+        assert!(matches!(
+            Snapshot::load(&path, &BrainConfig::default()),
+            Err(SnapshotError::NotASnapshot)
+        ));
+
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend((SNAPSHOT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            Snapshot::load(&path, &BrainConfig::default()),
+            Err(SnapshotError::UnsupportedVersion(version)) if version == SNAPSHOT_VERSION + 1
+        ));
+
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend(SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend([1, 2, 3]);
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            Snapshot::load(&path, &BrainConfig::default()),
+            Err(SnapshotError::Encoding(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_snapshots() {
+        let brain = BrainConfig::default();
+        let invalid = |change: fn(&mut Snapshot)| {
+            let mut snapshot = test_snapshot();
+            change(&mut snapshot);
+            matches!(snapshot.validate(&brain), Err(SnapshotError::Invalid(_)))
+        };
+        assert!(test_snapshot().validate(&brain).is_ok());
+        assert!(invalid(
+            |snapshot| snapshot.organisms[0].position = Vec2::new(10.0, 3.0)
+        ));
+        assert!(invalid(
+            |snapshot| snapshot.organisms[0].position = Vec2::new(-1.0, 3.0)
+        ));
+        assert!(invalid(
+            |snapshot| snapshot.organisms[0].position = Vec2::new(f32::NAN, 3.0)
+        ));
+        assert!(invalid(
+            |snapshot| snapshot.organisms[1].position = Vec2::new(1.0, 1.0)
+        ));
+        assert!(invalid(|snapshot| snapshot
+            .environment
+            .obstacles
+            .set(2, 1, true)));
+        assert!(invalid(|snapshot| {
+            snapshot.environment.food.data.pop();
+        }));
+        assert!(invalid(
+            |snapshot| snapshot.environment.pheromones = Grid2d::new(5, 20)
+        ));
+        assert!(invalid(|snapshot| {
+            snapshot.organisms[0].brain = NeuralNet::new(vec![3, 1, 2]);
+        }));
+
+        //a config with an extra sensor would feed the inputs into the wrong neurons
+        let path = std::env::temp_dir().join("evo_reject_invalid_snapshots.evo");
+        test_snapshot().save(&path).unwrap();
+        assert!(Snapshot::load(&path, &brain).is_ok());
+        let mut other = BrainConfig::default();
+        other.sensors.push(Sensor::Age);
+        assert!(matches!(
+            Snapshot::load(&path, &other),
+            Err(SnapshotError::BrainMismatch { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
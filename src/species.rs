@@ -0,0 +1,166 @@
+use crate::{
+    config::Config, environment::Environment, gene::Genome, generation::Generation,
+    organism::Organism, stats::Statistics, systems::cell_index,
+};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+//genomes within the species threshold of a representative genome
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Species {
+    pub id: usize,
+    pub representative: Genome,
+    //generation the species appeared in
+    pub born: usize,
+    pub size: usize,
+}
+
+//the living species, organisms are sorted into them at the start of every generation.
+//Ids are never reused, a species keeps its id as long as it has members
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpeciesTracker {
+    pub species: Vec<Species>,
+    //species that lost their last member in the latest assignment
+    pub extinct: Vec<Species>,
+    next_id: usize,
+    //generation the organisms were last sorted in
+    generation: Option<usize>,
+}
+
+impl SpeciesTracker {
+    //sorts the genomes into species, returns the species id of each genome. Every genome
+    //joins the closest species whose representative is within `threshold` (see
+    //Genome::distance) or founds a new one. The first member becomes the new representative
+    pub fn assign(&mut self, genomes: &[&Genome], generation: usize, threshold: f32) -> Vec<usize> {
+        let mut ids = Vec::with_capacity(genomes.len());
+        let mut representatives: HashMap<usize, Genome> = HashMap::new();
+        for genome in genomes {
+            let closest = self
+                .species
+                .iter()
+                .map(|species| (species.id, genome.distance(&species.representative)))
+                .filter(|(_, distance)| *distance <= threshold)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(id, _)| id);
+
+            let id = closest.unwrap_or_else(|| {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.species.push(Species {
+                    id,
+                    representative: (*genome).clone(),
+                    born: generation,
+                    size: 0,
+                });
+                id
+            });
+            representatives
+                .entry(id)
+                .or_insert_with(|| (*genome).clone());
+            ids.push(id);
+        }
+
+        let mut sizes: HashMap<usize, usize> = HashMap::new();
+        for id in &ids {
+            *sizes.entry(*id).or_default() += 1;
+        }
+        let (living, extinct) = self
+            .species
+            .drain(..)
+            .partition(|species| sizes.contains_key(&species.id));
+        self.species = living;
+        self.extinct = extinct;
+        for species in self.species.iter_mut() {
+            species.size = sizes[&species.id];
+            species.representative = representatives.remove(&species.id).unwrap();
+        }
+        self.generation = Some(generation);
+
+        ids
+    }
+}
+
+//evenly spread hues so consecutive species look different
+pub fn species_color(id: usize) -> Color {
+    Color::hsl((id as f32 * 137.508) % 360.0, 0.8, 0.5)
+}
+
+//sorts the organisms of a new generation (or the population of a new or restored run) into
+//species
+pub fn assign_species(
+    config: Res<Config>,
+    environment: Res<Environment>,
+    generation: Res<Generation>,
+    mut tracker: ResMut<SpeciesTracker>,
+    mut statistics: Option<ResMut<Statistics>>,
+    mut organisms: Query<&mut Organism>,
+) {
+    if !config.species.enabled || tracker.generation == Some(generation.count) {
+        return;
+    }
+
+    //sorted by cell like in environment_step, so the ids don't depend on the query order
+    let mut organisms: Vec<Mut<Organism>> = organisms.iter_mut().collect();
+    organisms.sort_by_key(|organism| cell_index(&environment, organism));
+    let genomes: Vec<&Genome> = organisms.iter().map(|organism| &organism.genome).collect();
+    let ids = tracker.assign(&genomes, generation.count, config.species.threshold);
+    for (organism, id) in organisms.iter_mut().zip(ids) {
+        organism.species = Some(id);
+    }
+
+    if let Some(statistics) = statistics.as_mut() {
+        if let Err(error) = statistics.record_species(generation.count, &tracker) {
+            error!("failed to write species timeline: {}", error);
+        }
+    }
+}
+
+//colours newly spawned organisms by species instead of by genome, organisms are sorted into
+//species as soon as they are spawned (see assign_species)
+pub fn color_species(
+    config: Res<Config>,
+    mut organisms: Query<(&Organism, &mut Fill), Added<Organism>>,
+) {
+    if !config.species.color_by_species {
+        return;
+    }
+    for (organism, mut fill) in organisms.iter_mut() {
+        if let Some(id) = organism.species {
+            fill.color = species_color(id);
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::gene::Gene;
+
+    fn genome(bits: &[u32]) -> Genome {
+        Genome::from_genes(
+            vec![2, 2],
+            bits.iter().map(|bits| Gene::from_bits(*bits)).collect(),
+        )
+    }
+
+    #[test]
+    fn stable_species() {
+        let mut tracker = SpeciesTracker::default();
+        let (a, a2) = (genome(&[0x0000_0000]), genome(&[0x0000_0001]));
+        let (b, c) = (genome(&[0xffff_ffff]), genome(&[0x00ff_ff00]));
+
+        assert!(tracker.assign(&[&a, &b, &a2], 0, 0.1) == vec![0, 1, 0]);
+        assert!(tracker.species.iter().map(|s| s.size).collect::<Vec<_>>() == vec![2, 1]);
+
+        //b dies out, c founds a new species, a keeps its id
+        assert!(tracker.assign(&[&c, &a2], 1, 0.1) == vec![2, 0]);
+        assert!(tracker.extinct.len() == 1 && tracker.extinct[0].id == 1);
+        let ids: Vec<(usize, usize)> = tracker.species.iter().map(|s| (s.id, s.born)).collect();
+        assert!(ids == vec![(0, 0), (2, 1)]);
+        assert!(tracker.species[0].representative == a2);
+
+        //ids aren't reused once a species is extinct
+        assert!(tracker.assign(&[&b], 2, 0.1) == vec![3]);
+    }
+}
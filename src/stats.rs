@@ -0,0 +1,323 @@
+use crate::{
+    config::StatsConfig,
+    gene::{diversity, Gene},
+    organism::Organism,
+    species::SpeciesTracker,
+};
+use bevy::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+//the hue distribution is counted in buckets of 30 degrees
+pub const HUE_BUCKETS: usize = 12;
+
+//summary of a finished generation, one csv row / json line
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GenerationStats {
+    pub generation: usize,
+    //organisms alive at the end of the generation
+    pub population: usize,
+    pub survivors: usize,
+    pub survival_rate: f32,
+    //in genes
+    pub genome_length_mean: f32,
+    pub genome_length_variance: f32,
+    pub distinct_genomes: usize,
+    //mean distance between two genomes, see gene::diversity
+    pub diversity: f32,
+    //species among the organisms, 0 unless species are tracked
+    pub species: usize,
+    pub mean_weight: f32,
+    //organisms by the hue of Genome::get_color, bucket i holds hues from i * 30 degrees
+    pub hues: [usize; HUE_BUCKETS],
+    //wall clock time the generation took
+    pub seconds: f64,
+}
+
+impl GenerationStats {
+    pub fn collect(
+        generation: usize,
+        organisms: &[Organism],
+        survivors: usize,
+        elapsed: Duration,
+    ) -> Self {
+        let population = organisms.len();
+        let count = population.max(1) as f32;
+
+        let lengths: Vec<f32> = organisms
+            .iter()
+            .map(|organism| organism.genome.length() as f32)
+            .collect();
+        let genome_length_mean = lengths.iter().sum::<f32>() / count;
+        let genome_length_variance = lengths
+            .iter()
+            .map(|length| (length - genome_length_mean).powi(2))
+            .sum::<f32>()
+            / count;
+
+        let distinct_genomes = organisms
+            .iter()
+            .map(|organism| (organism.genome.layers(), organism.genome.genes()))
+            .collect::<HashSet<_>>()
+            .len();
+        let genomes: Vec<_> = organisms.iter().map(|organism| &organism.genome).collect();
+        let species = organisms
+            .iter()
+            .filter_map(|organism| organism.species)
+            .collect::<HashSet<_>>()
+            .len();
+
+        let weights: Vec<f32> = organisms
+            .iter()
+            .flat_map(|organism| organism.genome.genes().iter().map(Gene::get_weight))
+            .collect();
+        let mean_weight = weights.iter().sum::<f32>() / weights.len().max(1) as f32;
+
+        let mut hues = [0; HUE_BUCKETS];
+        for organism in organisms {
+            let hue = organism.genome.get_color().hue as usize % 360;
+            hues[hue * HUE_BUCKETS / 360] += 1;
+        }
+
+        GenerationStats {
+            generation,
+            population,
+            survivors,
+            survival_rate: survivors as f32 / count,
+            genome_length_mean,
+            genome_length_variance,
+            distinct_genomes,
+            diversity: diversity(&genomes),
+            species,
+            mean_weight,
+            hues,
+            seconds: elapsed.as_secs_f64(),
+        }
+    }
+
+    pub fn csv_header() -> String {
+        let mut columns = vec![
+            String::from("generation"),
+            String::from("population"),
+            String::from("survivors"),
+            String::from("survival_rate"),
+            String::from("genome_length_mean"),
+            String::from("genome_length_variance"),
+            String::from("distinct_genomes"),
+            String::from("diversity"),
+            String::from("species"),
+            String::from("mean_weight"),
+        ];
+        columns
+            .extend((0..HUE_BUCKETS).map(|bucket| format!("hue_{}", bucket * 360 / HUE_BUCKETS)));
+        columns.push(String::from("seconds"));
+        columns.join(",")
+    }
+
+    pub fn csv_row(&self) -> String {
+        let mut columns = vec![
+            self.generation.to_string(),
+            self.population.to_string(),
+            self.survivors.to_string(),
+            self.survival_rate.to_string(),
+            self.genome_length_mean.to_string(),
+            self.genome_length_variance.to_string(),
+            self.distinct_genomes.to_string(),
+            self.diversity.to_string(),
+            self.species.to_string(),
+            self.mean_weight.to_string(),
+        ];
+        columns.extend(self.hues.iter().map(|count| count.to_string()));
+        columns.push(self.seconds.to_string());
+        columns.join(",")
+    }
+}
+
+//writes the statistics of every generation to the files in the [stats] config section.
+//Every line is flushed right away so runs can be plotted while they are going
+#[derive(Resource)]
+pub struct Statistics {
+    started: Instant,
+    csv: Option<BufWriter<File>>,
+    jsonl: Option<BufWriter<File>>,
+    species: Option<BufWriter<File>>,
+}
+
+fn create(path: &Path) -> io::Result<BufWriter<File>> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error)))
+}
+
+impl Statistics {
+    //None when no output file is configured
+    pub fn create(config: &StatsConfig) -> io::Result<Option<Statistics>> {
+        if config.csv.is_none() && config.jsonl.is_none() && config.species.is_none() {
+            return Ok(None);
+        }
+
+        let mut csv = config.csv.as_deref().map(create).transpose()?;
+        if let Some(csv) = csv.as_mut() {
+            writeln!(csv, "{}", GenerationStats::csv_header())?;
+            csv.flush()?;
+        }
+        let jsonl = config.jsonl.as_deref().map(create).transpose()?;
+        let mut species = config.species.as_deref().map(create).transpose()?;
+        if let Some(species) = species.as_mut() {
+            writeln!(species, "generation,species,size,born,event")?;
+            species.flush()?;
+        }
+
+        Ok(Some(Statistics {
+            started: Instant::now(),
+            csv,
+            jsonl,
+            species,
+        }))
+    }
+
+    pub fn record(
+        &mut self,
+        generation: usize,
+        organisms: &[Organism],
+        survivors: usize,
+    ) -> io::Result<()> {
+        let stats =
+            GenerationStats::collect(generation, organisms, survivors, self.started.elapsed());
+        self.started = Instant::now();
+
+        if let Some(csv) = self.csv.as_mut() {
+            writeln!(csv, "{}", stats.csv_row())?;
+            csv.flush()?;
+        }
+        if let Some(jsonl) = self.jsonl.as_mut() {
+            serde_json::to_writer(&mut *jsonl, &stats)?;
+            writeln!(jsonl)?;
+            jsonl.flush()?;
+        }
+        Ok(())
+    }
+
+    //a timeline row per living species, born marks a species that appeared this generation.
+    //Species that died out get a last row of size 0 marked extinct
+    pub fn record_species(
+        &mut self,
+        generation: usize,
+        tracker: &SpeciesTracker,
+    ) -> io::Result<()> {
+        let Some(file) = self.species.as_mut() else {
+            return Ok(());
+        };
+        for species in &tracker.species {
+            let event = if species.born == generation {
+                "born"
+            } else {
+                ""
+            };
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                generation, species.id, species.size, species.born, event
+            )?;
+        }
+        for species in &tracker.extinct {
+            writeln!(
+                file,
+                "{},{},0,{},extinct",
+                generation, species.id, species.born
+            )?;
+        }
+        file.flush()
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::{
+        neural::{BrainState, NeuralNet},
+        organism::Direction,
+    };
+    use bevy::math::Vec2;
+
+    fn organism(brain: NeuralNet) -> Organism {
+        Organism {
+            genome: brain.clone().into(),
+            brain,
+            position: Vec2::ZERO,
+            heading: Direction::North,
+            last_move: None,
+            oscillator_period: None,
+            energy: 0.0,
+            species: None,
+            memory: BrainState::default(),
+        }
+    }
+
+    #[test]
+    fn collect_stats() {
+        let rng = &mut rand::thread_rng();
+        let mut brain = NeuralNet::new(vec![2, 2]);
+        brain.init_random_connections(3, (-1.0, 1.0), rng);
+        let organisms = vec![
+            organism(brain.clone()),
+            organism(brain),
+            organism(NeuralNet::new(vec![2, 2])),
+        ];
+        let genes = organisms[0].genome.length() as f32;
+
+        let stats = GenerationStats::collect(4, &organisms, 1, Duration::from_secs(2));
+        assert!(stats.population == 3 && stats.survivors == 1);
+        assert!(stats.survival_rate == 1.0 / 3.0);
+        assert!(stats.distinct_genomes == 2);
+        assert!(stats.diversity > 0.0);
+        assert!((stats.genome_length_mean - genes * 2.0 / 3.0).abs() < 1e-5);
+        assert!(stats.genome_length_variance > 0.0);
+        assert!(stats.hues.iter().sum::<usize>() == 3);
+        assert!(stats.seconds == 2.0);
+        assert!(
+            stats.csv_row().split(',').count() == GenerationStats::csv_header().split(',').count()
+        );
+
+        let empty = GenerationStats::collect(0, &[], 0, Duration::ZERO);
+        assert!(empty.survival_rate == 0.0 && empty.mean_weight == 0.0);
+    }
+
+    #[test]
+    fn write_files() {
+        let directory = std::env::temp_dir();
+        let config = StatsConfig {
+            csv: Some(directory.join("evo_write_files.csv")),
+            jsonl: Some(directory.join("evo_write_files.jsonl")),
+            species: None,
+        };
+        assert!(Statistics::create(&StatsConfig::default())
+            .unwrap()
+            .is_none());
+
+        let mut statistics = Statistics::create(&config).unwrap().unwrap();
+        let organisms = vec![organism(NeuralNet::new(vec![2, 2]))];
+        statistics.record(0, &organisms, 1).unwrap();
+        statistics.record(1, &organisms, 0).unwrap();
+
+        let csv = std::fs::read_to_string(config.csv.as_ref().unwrap()).unwrap();
+        assert!(csv.lines().count() == 3);
+        assert!(csv.starts_with("generation,population,survivors,"));
+        let jsonl = std::fs::read_to_string(config.jsonl.as_ref().unwrap()).unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(lines.len() == 2);
+        assert!(lines[1]["generation"] == 1 && lines[1]["survival_rate"] == 0.0);
+        assert!(lines[0]["hues"].as_array().unwrap().len() == HUE_BUCKETS);
+
+        std::fs::remove_file(config.csv.unwrap()).unwrap();
+        std::fs::remove_file(config.jsonl.unwrap()).unwrap();
+    }
+}
@@ -1,252 +1,666 @@
 use crate::{
+    environment::heading_rotation,
     environment::Environment,
-    environment::SimulationSpeed,
-    organism::{Action, Direction, Organism, OrganismUpdateStore},
-    utils::{vec2_to_i32, Grid2d},
+    generation::{next_generation, Generation, GenerationLimit},
+    neural::CompiledNet,
+    organism::{Action, CompiledBrain, Direction, Organism, PollBuffers},
+    sensors::SenseContext,
+    stats::Statistics,
+    utils::{organism_rng, vec2_to_i32, Grid2d, SimRng},
+};
+use bevy::{
+    color::palettes::css::ORANGE,
+    prelude::*,
+    tasks::{ComputeTaskPool, ParallelSlice, TaskPool},
+};
+use bevy_prototype_lyon::prelude::*;
+use rand::Rng;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ops::Range,
 };
-use bevy::prelude::*;
-use std::collections::HashMap;
 
-pub fn poll_organism(
-    organism_store: &HashMap<u32, Organism>,
-    update_store: &mut OrganismUpdateStore,
-    organism: Organism,
-    environment: &Environment,
-) -> Action {
-    let organism_id = environment
-        .organisms
-        .get(organism.position.x as usize, organism.position.y as usize)
-        .unwrap();
-
-    let normalized_x = organism.position.x / environment.organisms.width as f32;
-    let normalized_y = organism.position.y / environment.organisms.height as f32;
-
-    if let Some(polled_direction) = organism.poll(vec![normalized_x, normalized_y]) {
-        let intended_position =
-            vec2_to_i32(calculate_new_position(polled_direction, organism.position));
-
-        let in_bounds = match polled_direction {
-            Direction::West => organism.position.x as i32 > 0,
-            Direction::East => {
-                (organism.position.x as i32) < (environment.organisms.width as i32) - 1
-            }
-            Direction::North => {
-                (organism.position.y as i32) < (environment.organisms.height as i32) - 1
-            }
-            Direction::South => (organism.position.y as i32) > 0,
-            Direction::SouthEast => {
-                (organism.position.x as i32) < (environment.organisms.width as i32) - 1
-                    && (organism.position.y as i32) > 0
-            }
+//what an organism's brain decided this step, independently of every other organism. Its
+//other actions and memory are ranges into the Polled it belongs to
+pub struct Intent {
+    pub direction: Option<Direction>,
+    pub actions: Range<usize>,
+    pub memory: Range<usize>,
+    //decides which of the organisms moving into the same cell gets it
+    pub priority: u64,
+    //the resolved move, Action::Nothing if the organism stays
+    pub action: Action,
+}
 
-            Direction::SouthWest => {
-                (organism.position.x as i32) > 0 && (organism.position.y as i32) > 0
-            }
+//the intents of polled organisms, with their actions and memories stored back to back so
+//polling doesn't allocate for every organism
+#[derive(Default)]
+pub struct Polled {
+    pub intents: Vec<Intent>,
+    actions: Vec<Action>,
+    memories: Vec<f32>,
+}
+
+impl Polled {
+    fn push(&mut self, direction: Option<Direction>, buffers: &PollBuffers, priority: u64) {
+        let actions = self.actions.len()..self.actions.len() + buffers.actions.len();
+        self.actions.extend_from_slice(&buffers.actions);
+        let memory = self.memories.len()..self.memories.len() + buffers.memory.activations.len();
+        self.memories.extend_from_slice(&buffers.memory.activations);
+        self.intents.push(Intent {
+            direction,
+            actions,
+            memory,
+            priority,
+            action: Action::Nothing,
+        });
+    }
+
+    fn append(&mut self, other: Polled) {
+        let (actions, memories) = (self.actions.len(), self.memories.len());
+        self.intents
+            .extend(other.intents.into_iter().map(|intent| Intent {
+                actions: intent.actions.start + actions..intent.actions.end + actions,
+                memory: intent.memory.start + memories..intent.memory.end + memories,
+                ..intent
+            }));
+        self.actions.extend(other.actions);
+        self.memories.extend(other.memories);
+    }
+
+    //the actions other than moving of the nth organism
+    pub fn actions(&self, index: usize) -> &[Action] {
+        &self.actions[self.intents[index].actions.clone()]
+    }
+
+    //the brain state of the nth organism after the poll
+    pub fn memory(&self, index: usize) -> &[f32] {
+        &self.memories[self.intents[index].memory.clone()]
+    }
+}
 
-            Direction::NorthEast => {
-                (organism.position.x as i32) < (environment.organisms.width as i32) - 1
-                    && (organism.position.y as i32) < (environment.organisms.height as i32) - 1
+//an organism at `from` moving into `to`, None if it stays or can't move there
+#[derive(Clone, Copy, Debug)]
+pub struct MoveRequest {
+    pub from: (usize, usize),
+    pub to: Option<(usize, usize)>,
+    pub priority: u64,
+}
+
+//resolves every move at once, returns whether each organism moves. A cell wanted by several
+//organisms goes to the one with the highest priority (the first in row major order on a tie),
+//the others stay. An organism moving into an occupied cell only moves if the occupant moves
+//away, so a chain of movers moves if its head reaches an empty cell. Cycles of movers (two
+//organisms swapping cells included) all move, every cell is vacated as it's entered. The
+//result doesn't depend on the order of the requests, organisms must be on distinct cells
+pub fn resolve_moves(requests: &[MoveRequest]) -> Vec<bool> {
+    let rank = |index: usize| {
+        let request = &requests[index];
+        (request.priority, Reverse((request.from.1, request.from.0)))
+    };
+    let mut claims: HashMap<(usize, usize), usize> = HashMap::new();
+    for (index, request) in requests.iter().enumerate() {
+        if let Some(to) = request.to {
+            let claimant = claims.entry(to).or_insert(index);
+            if rank(index) > rank(*claimant) {
+                *claimant = index;
             }
+        }
+    }
+    let occupants: HashMap<(usize, usize), usize> = requests
+        .iter()
+        .enumerate()
+        .map(|(index, request)| (request.from, index))
+        .collect();
 
-            Direction::NorthWest => {
-                (organism.position.x as i32) > 0
-                    && (organism.position.y as i32) < (environment.organisms.height as i32) - 1
+    //every cell has at most one claimant now, so the movers form chains and cycles
+    let mut moves: Vec<Option<bool>> = requests
+        .iter()
+        .enumerate()
+        .map(|(index, request)| match request.to {
+            Some(to) if claims[&to] == index => None,
+            _ => Some(false),
+        })
+        .collect();
+    let mut on_path = vec![false; requests.len()];
+    for start in 0..requests.len() {
+        let mut path = Vec::new();
+        let mut current = start;
+        let result = loop {
+            if let Some(result) = moves[current] {
+                break result;
             }
-        };
-        if !in_bounds {
-            update_store.add_organism(
-                *organism_id,
-                vec2_to_i32(organism.position),
-                Action::Nothing,
-            );
-            return Action::Nothing;
-        }
-
-        match update_store.get(intended_position) {
-            Some((id, action)) => {
-                if id == organism_id {
-                    // Then update the store
-                    *action
-                } else {
-                    //if organism already intends to move to that position do nothing
-                    update_store.add_organism(
-                        *organism_id,
-                        vec2_to_i32(organism.position),
-                        Action::Nothing,
-                    );
-                    Action::Nothing
-                }
+            //back at the start of the path, a cycle
+            if on_path[current] {
+                break true;
             }
-            None => {
-                //check if organism is currently at that position
-                match environment
-                    .organisms
-                    .get(intended_position.0 as usize, intended_position.1 as usize)
-                {
-                    Some(id) => {
-                        //poll to see if they're going to move
-                        let action = poll_organism(
-                            organism_store,
-                            update_store,
-                            organism_store.get(id).unwrap().clone(),
-                            environment,
-                        );
-
-                        match action {
-                            Action::Nothing => {
-                                //occupying organism will remoain in that position
-                                update_store.add_organism(
-                                    *organism_id,
-                                    vec2_to_i32(organism.position),
-                                    Action::Nothing,
-                                );
-                                Action::Nothing
-                            }
-
-                            Action::Move(_) => {
-                                //occupying organism will move away
-                                update_store.add_organism(
-                                    *organism_id,
-                                    intended_position,
-                                    Action::Move(polled_direction),
-                                );
-                                Action::Move(polled_direction)
-                            }
-                        }
-                    }
-
-                    None => {
-                        update_store.add_organism(
-                            *organism_id,
-                            vec2_to_i32(calculate_new_position(
-                                polled_direction,
-                                organism.position,
-                            )),
-                            Action::Move(polled_direction),
-                        );
-                        Action::Move(polled_direction)
-                    }
-                }
+            on_path[current] = true;
+            path.push(current);
+            match occupants.get(&requests[current].to.unwrap()) {
+                Some(occupant) => current = *occupant,
+                None => break true,
             }
+        };
+        for index in path {
+            moves[index] = Some(result);
         }
-    } else {
-        update_store.add_organism(
-            *organism_id,
-            vec2_to_i32(organism.position),
-            Action::Nothing,
-        );
-        Action::Nothing
     }
 
-    //check if organism is in bounds
+    moves.into_iter().map(|result| result.unwrap()).collect()
 }
 
-pub fn poll_organisms(
-    environment: &mut Environment,
-    organisms: Vec<(Organism, Entity)>,
-) -> Vec<Action> {
-    let mut directions = Vec::new();
+//the cell of the organism in row major order, the order organisms are stepped in
+pub fn cell_index(environment: &Environment, organism: &Organism) -> usize {
+    let (x, y) = vec2_to_i32(organism.position);
+    y as usize * environment.organisms.width + x as usize
+}
 
-    let organism_store: HashMap<u32, Organism> = organisms
+//polls every organism with its compiled brain (by entity bits) in parallel. Each organism gets
+//its own random source from the step seed, so the result doesn't depend on the order of
+//`organisms`
+pub fn poll_organisms(
+    environment: &Environment,
+    organisms: &[(&Organism, Entity)],
+    brains: &HashMap<u64, &CompiledNet>,
+    generation: &Generation,
+    step_seed: u64,
+) -> Polled {
+    let organism_store: HashMap<u64, &Organism> = organisms
         .iter()
-        .map(|(organism, entity)| (entity.index(), organism.clone()))
+        .map(|(organism, entity)| (entity.to_bits(), *organism))
         .collect();
+    let context = SenseContext {
+        environment,
+        organisms: &organism_store,
+        generation,
+    };
 
-    let mut update_store: OrganismUpdateStore = OrganismUpdateStore::new();
+    let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+    let mut polled = Polled::default();
+    for chunk in organisms.par_splat_map(pool, None, |_, chunk| {
+        let mut buffers = PollBuffers::default();
+        let mut polled = Polled::default();
+        for (organism, entity) in chunk {
+            let mut rng = organism_rng(step_seed, cell_index(environment, organism));
+            let brain = brains[&entity.to_bits()];
+            let direction = organism.poll(brain, &context, &mut buffers, &mut rng);
+            polled.push(direction, &buffers, rng.gen());
+        }
+        polled
+    }) {
+        polled.append(chunk);
+    }
+    polled
+}
+
+//the organisms (by entity bits) killed by the polled organisms, in the same order. Kills happen
+//in that order before anyone moves, an organism killed by one before it doesn't get to kill
+pub fn resolve_kills(
+    environment: &Environment,
+    organisms: &[(Entity, Vec2)],
+    polled: &Polled,
+) -> HashSet<u64> {
+    let mut dead: HashSet<u64> = HashSet::new();
+    for (index, (entity, position)) in organisms.iter().enumerate() {
+        if dead.contains(&entity.to_bits()) {
+            continue;
+        }
+        for action in polled.actions(index) {
+            if let Action::Kill(direction) = action {
+                let (x, y) =
+                    vec2_to_i32(calculate_new_position(environment, *direction, *position));
+                let Some((x, y)) = environment.wrap(x, y) else {
+                    continue;
+                };
+                if let Some(victim) = environment.organisms.get(x, y) {
+                    dead.insert(*victim);
+                }
+            }
+        }
+    }
+    dead
+}
 
-    //check if all ids  in environment.organisms are in organism_store
+//resolves the moves of the polled organisms that weren't killed into Intent::action. The cells
+//of the dead are empty, they can't take a cell from a living organism or block a chain of
+//movers
+pub fn resolve_intents(
+    environment: &Environment,
+    organisms: &[(Entity, Vec2)],
+    dead: &HashSet<u64>,
+    polled: &mut Polled,
+) {
+    let living: Vec<usize> = (0..organisms.len())
+        .filter(|index| !dead.contains(&organisms[*index].0.to_bits()))
+        .collect();
+    let requests: Vec<MoveRequest> = living
+        .iter()
+        .map(|index| {
+            let (_, position) = organisms[*index];
+            let intent = &polled.intents[*index];
+            let from = vec2_to_i32(position);
+            //outside the grid or into a wall
+            let to = intent
+                .direction
+                .map(|direction| {
+                    vec2_to_i32(calculate_new_position(environment, direction, position))
+                })
+                .filter(|(x, y)| environment.is_passable(*x, *y));
+            MoveRequest {
+                from: (from.0 as usize, from.1 as usize),
+                to: to.map(|(x, y)| (x as usize, y as usize)),
+                priority: intent.priority,
+            }
+        })
+        .collect();
 
-    for (organism, _) in organisms {
-        let action = poll_organism(&organism_store, &mut update_store, organism, environment);
-        directions.push(action);
+    for (index, moves) in living.into_iter().zip(resolve_moves(&requests)) {
+        let intent = &mut polled.intents[index];
+        if let (Some(direction), true) = (intent.direction, moves) {
+            intent.action = Action::Move(direction);
+        }
     }
+}
 
-    directions
+pub fn exit_after_generations(
+    generation: Res<Generation>,
+    limit: Res<GenerationLimit>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if generation.count >= limit.0 {
+        info!("finished {} generations", generation.count);
+        exit.send(AppExit::Success);
+    }
 }
 
 pub fn environment_step(
     mut env: ResMut<Environment>,
-    time: Res<Time>,
-    mut simulation_speed: ResMut<SimulationSpeed>,
-    mut organism_query: Query<(&mut Organism, Entity, &mut Transform)>,
+    mut commands: Commands,
+    mut generation: ResMut<Generation>,
+    mut rng: ResMut<SimRng>,
+    mut organism_query: Query<(&mut Organism, Entity, Option<&mut Transform>)>,
+    brain_query: Query<(&CompiledBrain, Entity)>,
+    mut statistics: Option<ResMut<Statistics>>,
 ) {
-    if simulation_speed.timer.tick(time.delta()).just_finished() {
-        let organisms: Vec<(Organism, Entity)> = organism_query
+    //everything below happens in cell order, whatever order the query returns organisms in
+    let brains: HashMap<u64, &CompiledNet> = brain_query
+        .iter()
+        .map(|(brain, entity)| (entity.to_bits(), &brain.0))
+        .collect();
+    let (organisms, mut polled) = {
+        let mut organisms: Vec<(&Organism, Entity)> = organism_query
             .iter()
-            .map(|(organism, entity, _)| (organism.clone(), entity))
+            .map(|(organism, entity, _)| (organism, entity))
+            .collect();
+        organisms.sort_by_key(|(organism, _)| cell_index(&env, organism));
+        let polled = poll_organisms(&env, &organisms, &brains, &generation, rng.gen());
+        let organisms: Vec<(Entity, Vec2)> = organisms
+            .into_iter()
+            .map(|(organism, entity)| (entity, organism.position))
             .collect();
+        (organisms, polled)
+    };
 
-        let next_actions = poll_organisms(&mut env, organisms);
+    //kills happen before anyone moves. A killed organism doesn't act
+    let mut dead = resolve_kills(&env, &organisms, &polled);
+    resolve_intents(&env, &organisms, &dead, &mut polled);
+    for (entity, _) in &organisms {
+        if dead.contains(&entity.to_bits()) {
+            commands.entity(*entity).despawn();
+        }
+    }
+    let energy = generation.energy.clone();
 
-        env.organisms = Grid2d::new(env.organisms.width, env.organisms.height);
+    env.organisms = Grid2d::new(env.organisms.width, env.organisms.height);
+    env.decay_pheromones();
 
-        for ((mut organism, entity, mut transform), action) in
-            organism_query.iter_mut().zip(next_actions.iter())
-        {
-            match action {
-                Action::Move(direction) => {
-                    let (target_grid_x, target_grid_y) = match direction {
-                        Direction::West => (organism.position.x - 1.0, organism.position.y),
-                        Direction::East => (organism.position.x + 1.0, organism.position.y),
-                        Direction::North => (organism.position.x, organism.position.y + 1.0),
-                        Direction::South => (organism.position.x, organism.position.y - 1.0),
-                        Direction::SouthEast => {
-                            (organism.position.x + 1.0, organism.position.y - 1.0)
-                        }
-                        Direction::SouthWest => {
-                            (organism.position.x - 1.0, organism.position.y - 1.0)
-                        }
-
-                        Direction::NorthEast => {
-                            (organism.position.x + 1.0, organism.position.y + 1.0)
-                        }
-
-                        Direction::NorthWest => {
-                            (organism.position.x - 1.0, organism.position.y + 1.0)
-                        }
-                    };
-
-                    // Calculate new world position
-                    let new_x = (target_grid_x * env.organism_size) - (env.width / 2.0)
-                        + (env.organism_size / 2.0);
-                    let new_y = (target_grid_y * env.organism_size) - (env.height / 2.0)
-                        + (env.organism_size / 2.0);
-
-                    // Update position
-                    organism.position = Vec2::new(target_grid_x, target_grid_y);
-                    transform.translation.x = new_x;
-                    transform.translation.y = new_y;
-
-                    // Update environment grid with new position
-                    env.organisms.set(
-                        target_grid_x as usize,
-                        target_grid_y as usize,
-                        entity.index(),
-                    );
-
-                    //update environment
+    for (index, (entity, _)) in organisms.iter().enumerate() {
+        if dead.contains(&entity.to_bits()) {
+            continue;
+        }
+        let action = &polled.intents[index].action;
+        let Ok((mut organism, entity, mut transform)) = organism_query.get_mut(*entity) else {
+            continue;
+        };
+
+        match action {
+            Action::Move(direction) => {
+                let target = calculate_new_position(&env, *direction, organism.position);
+                let (target_grid_x, target_grid_y) = (target.x, target.y);
+
+                // Update position
+                organism.position = Vec2::new(target_grid_x, target_grid_y);
+                organism.heading = *direction;
+                organism.last_move = Some(*direction);
+                if let Some(transform) = transform.as_mut() {
+                    let world_position = env.cell_to_world(organism.position);
+                    transform.translation.x = world_position.x;
+                    transform.translation.y = world_position.y;
                 }
-                Action::Nothing => {
-                    env.organisms.set(
-                        organism.position.x as usize,
-                        organism.position.y as usize,
-                        entity.index(),
-                    );
+
+                // Update environment grid with new position
+                env.organisms.set(
+                    target_grid_x as usize,
+                    target_grid_y as usize,
+                    entity.to_bits(),
+                );
+
+                //update environment
+            }
+            _ => {
+                env.organisms.set(
+                    organism.position.x as usize,
+                    organism.position.y as usize,
+                    entity.to_bits(),
+                );
+            }
+        }
+
+        organism.memory.activations.clear();
+        organism
+            .memory
+            .activations
+            .extend_from_slice(polled.memory(index));
+        for action in polled.actions(index) {
+            match action {
+                Action::EmitPheromone => {
+                    let (x, y) = vec2_to_i32(organism.position);
+                    env.pheromones.set(x as usize, y as usize, 1.0);
                 }
+                Action::SetOscillatorPeriod(period) => organism.oscillator_period = Some(*period),
+                Action::Turn(steps) => organism.heading = organism.heading.rotate(*steps),
+                _ => {}
+            }
+        }
+
+        if let Some(transform) = transform.as_mut() {
+            transform.rotation = heading_rotation(organism.heading);
+        }
+
+        if energy.enabled {
+            organism.energy -= energy.step_cost;
+            if let Action::Move(_) = action {
+                organism.energy -= energy.move_cost;
+            }
+
+            let (x, y) = (organism.position.x as usize, organism.position.y as usize);
+            if env.food.get(x, y).is_some() {
+                env.food.set(x, y, false);
+                organism.energy = (organism.energy + energy.food_energy).min(energy.max);
+            }
+
+            if organism.energy <= 0.0 {
+                //starved
+                dead.insert(entity.to_bits());
+                commands.entity(entity).despawn();
+                env.organisms.set(x, y, 0);
+            }
+        }
+    }
+
+    if energy.enabled {
+        env.grow_food(energy.regrowth, energy.max_food, &mut *rng);
+    }
+
+    if generation.tick() {
+        let mut organisms: Vec<(Organism, Entity)> = organism_query
+            .iter()
+            .filter(|(_, entity, _)| !dead.contains(&entity.to_bits()))
+            .map(|(organism, entity, _)| (organism.clone(), entity))
+            .collect();
+        organisms.sort_by_key(|(organism, _)| cell_index(&env, organism));
+        let count = generation.count;
+        let population: Vec<Organism> = match statistics {
+            Some(_) => organisms
+                .iter()
+                .map(|(organism, _)| organism.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let survivors = next_generation(
+            &mut env,
+            &mut commands,
+            organisms,
+            &mut generation,
+            &mut *rng,
+        );
+
+        if let Some(statistics) = statistics.as_mut() {
+            if let Err(error) = statistics.record(count, &population, survivors) {
+                error!("failed to write statistics: {}", error);
             }
         }
     }
 }
 
-pub fn calculate_new_position(direction: Direction, position: Vec2) -> Vec2 {
-    match direction {
-        Direction::West => Vec2::new(position.x - 1.0, position.y),
-        Direction::East => Vec2::new(position.x + 1.0, position.y),
-        Direction::North => Vec2::new(position.x, position.y + 1.0),
-        Direction::South => Vec2::new(position.x, position.y - 1.0),
-        Direction::SouthEast => Vec2::new(position.x + 1.0, position.y - 1.0),
-        Direction::SouthWest => Vec2::new(position.x - 1.0, position.y - 1.0),
-        Direction::NorthEast => Vec2::new(position.x + 1.0, position.y + 1.0),
-        Direction::NorthWest => Vec2::new(position.x - 1.0, position.y + 1.0),
+//food shapes by cell, windowed mode only
+#[derive(Resource, Default)]
+pub struct FoodShapes(pub HashMap<(usize, usize), Entity>);
+
+//keeps a dot on every cell holding food
+pub fn draw_food(env: Res<Environment>, mut shapes: ResMut<FoodShapes>, mut commands: Commands) {
+    if !env.is_changed() {
+        return;
+    }
+
+    shapes.0.retain(|(x, y), entity| {
+        let eaten = env.food.get(*x, *y).is_none();
+        if eaten {
+            commands.entity(*entity).despawn();
+        }
+        !eaten
+    });
+
+    for y in 0..env.food.height {
+        for x in 0..env.food.width {
+            if env.food.get(x, y).is_none() || shapes.0.contains_key(&(x, y)) {
+                continue;
+            }
+            let center = env.cell_to_world(Vec2::new(x as f32, y as f32));
+            let entity = commands
+                .spawn((
+                    ShapeBundle {
+                        path: GeometryBuilder::build_as(&shapes::Circle {
+                            radius: env.organism_size / 4.0,
+                            ..shapes::Circle::default()
+                        }),
+                        spatial: SpatialBundle {
+                            transform: Transform::from_translation(center.extend(0.7)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Fill::color(Color::Srgba(ORANGE)),
+                ))
+                .id();
+            shapes.0.insert((x, y), entity);
+        }
+    }
+}
+
+//the neighbouring cell in the direction, across a wrapped edge if there is one. Moving
+//through a bounded edge gives a cell outside the grid, see Environment::is_passable
+pub fn calculate_new_position(
+    environment: &Environment,
+    direction: Direction,
+    position: Vec2,
+) -> Vec2 {
+    let (dx, dy) = direction.offset();
+    let (mut x, mut y) = (position.x as i32 + dx, position.y as i32 + dy);
+    if environment.topology.wraps_x() {
+        x = x.rem_euclid(environment.organisms.width as i32);
+    }
+    if environment.topology.wraps_y() {
+        y = y.rem_euclid(environment.organisms.height as i32);
+    }
+    Vec2::new(x as f32, y as f32)
+}
+
+mod tests {
+    use super::*;
+    use bevy::color::palettes::css::BLACK;
+    use rand::seq::SliceRandom;
+
+    fn request(from: (usize, usize), to: Option<(usize, usize)>, priority: u64) -> MoveRequest {
+        MoveRequest { from, to, priority }
+    }
+
+    #[test]
+    fn contested_cell() {
+        let requests = [
+            request((0, 1), Some((1, 1)), 5),
+            request((2, 1), Some((1, 1)), 9),
+            request((1, 0), Some((1, 1)), 1),
+        ];
+        assert!(resolve_moves(&requests) == vec![false, true, false]);
+
+        //on a tie the first cell in row major order wins
+        let requests = [
+            request((2, 1), Some((1, 1)), 5),
+            request((1, 0), Some((1, 1)), 5),
+        ];
+        assert!(resolve_moves(&requests) == vec![false, true]);
+    }
+
+    #[test]
+    fn chains() {
+        //a chain moving east into an empty cell
+        let mut requests = vec![
+            request((0, 0), Some((1, 0)), 0),
+            request((1, 0), Some((2, 0)), 0),
+            request((2, 0), Some((3, 0)), 0),
+        ];
+        assert!(resolve_moves(&requests) == vec![true; 3]);
+
+        //the head of the chain stays, so nobody behind it moves
+        requests.push(request((3, 0), None, 0));
+        assert!(resolve_moves(&requests) == vec![false; 4]);
+
+        //the head loses its cell to someone else
+        requests[3] = request((3, 1), Some((3, 0)), 1);
+        assert!(resolve_moves(&requests) == vec![false, false, false, true]);
+
+        //long chains don't recurse
+        let requests: Vec<MoveRequest> = (0..100_000)
+            .map(|x| request((x, 0), Some((x + 1, 0)), 0))
+            .collect();
+        assert!(resolve_moves(&requests).iter().all(|moves| *moves));
+    }
+
+    #[test]
+    fn dead_organisms_dont_block() {
+        // 10 x 10 grid
+        let environment = Environment::new(50.0, 50.0, 0.0, 0.0, Color::Srgba(BLACK), 2.0, 0, 5.0);
+        let intent = |direction: Option<Direction>, priority: u64| Intent {
+            direction,
+            actions: 0..0,
+            memory: 0..0,
+            priority,
+            action: Action::Nothing,
+        };
+        //a chain moving east behind an organism that stays, and an organism contesting a cell
+        //with one that outranks it
+        let organisms: Vec<(Entity, Vec2)> =
+            [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (5.0, 5.0), (4.0, 6.0)]
+                .into_iter()
+                .enumerate()
+                .map(|(index, (x, y))| (Entity::from_raw(index as u32 + 1), Vec2::new(x, y)))
+                .collect();
+        let intents = || {
+            vec![
+                intent(Some(Direction::East), 0),
+                intent(Some(Direction::East), 0),
+                intent(None, 0),
+                intent(Some(Direction::North), 9),
+                intent(Some(Direction::East), 1),
+            ]
+        };
+        let resolve = |dead: &[usize]| {
+            let dead: HashSet<u64> = dead
+                .iter()
+                .map(|index| organisms[*index].0.to_bits())
+                .collect();
+            let mut polled = Polled {
+                intents: intents(),
+                ..Polled::default()
+            };
+            resolve_intents(&environment, &organisms, &dead, &mut polled);
+            polled
+                .intents
+                .iter()
+                .map(|intent| intent.action != Action::Nothing)
+                .collect::<Vec<bool>>()
+        };
+
+        assert!(resolve(&[]) == vec![false, false, false, true, false]);
+        //the dead organisms' cells are empty, so the chain moves and the cell is free to take
+        assert!(resolve(&[2, 3]) == vec![true, true, false, false, true]);
+    }
+
+    #[test]
+    fn cycles() {
+        //four organisms rotating around a square, with one trying to join the cycle
+        let mut requests = vec![
+            request((0, 0), Some((1, 0)), 1),
+            request((1, 0), Some((1, 1)), 1),
+            request((1, 1), Some((0, 1)), 1),
+            request((0, 1), Some((0, 0)), 1),
+            request((2, 0), Some((1, 0)), 0),
+        ];
+        assert!(resolve_moves(&requests) == vec![true, true, true, true, false]);
+
+        //the newcomer wins a cell of the cycle, the organism it beat stays and blocks the
+        //chain that's left
+        requests[4].priority = 2;
+        assert!(resolve_moves(&requests) == vec![false; 5]);
+
+        //a swap is a cycle too
+        let requests = [
+            request((0, 0), Some((1, 0)), 0),
+            request((1, 0), Some((0, 0)), 0),
+        ];
+        assert!(resolve_moves(&requests) == vec![true, true]);
+    }
+
+    #[test]
+    fn resolution_ignores_order() {
+        let mut rng = rand::thread_rng();
+        let cells: Vec<(usize, usize)> = (0..8).flat_map(|y| (0..8).map(move |x| (x, y))).collect();
+        let mut requests: Vec<MoveRequest> = cells
+            .choose_multiple(&mut rng, 40)
+            .map(|from| {
+                let to = rng
+                    .gen_bool(0.8)
+                    .then(|| (rng.gen_range(0..8), rng.gen_range(0..8)));
+                request(*from, to, rng.gen_range(0..4))
+            })
+            .collect();
+
+        let resolve = |requests: &[MoveRequest]| {
+            let mut moves: Vec<((usize, usize), bool)> = requests
+                .iter()
+                .map(|request| request.from)
+                .zip(resolve_moves(requests))
+                .collect();
+            moves.sort();
+            moves
+        };
+        let expected = resolve(&requests);
+        for _ in 0..20 {
+            requests.shuffle(&mut rng);
+            assert!(resolve(&requests) == expected);
+        }
+
+        //every cell ends up with at most one organism
+        let mut targets = HashSet::new();
+        for (request, moves) in requests.iter().zip(resolve_moves(&requests)) {
+            let cell = match moves {
+                true => request.to.unwrap(),
+                false => request.from,
+            };
+            assert!(targets.insert(cell));
+        }
     }
 }
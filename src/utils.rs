@@ -1,10 +1,62 @@
 use std::collections::HashMap;
 
-use bevy::math::Vec2;
-use rand::Rng;
+use bevy::{math::Vec2, prelude::Resource};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
-pub fn generate_random_vec2(x_bounds: (f32, f32), y_bounds: (f32, f32)) -> Vec2 {
-    let mut rng = rand::thread_rng();
+//seedable random source shared by the whole simulation, two runs with the same seed
+//produce identical populations and movement
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct SimRng {
+    pub seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn from_entropy() -> Self {
+        SimRng::new(rand::thread_rng().gen())
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+//random source of a single organism for one step, seeded from the step seed and the
+//organism's cell so it doesn't depend on the order organisms are polled in
+pub fn organism_rng(step_seed: u64, cell: usize) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(step_seed);
+    rng.set_stream(cell as u64);
+    rng
+}
+
+pub fn generate_random_vec2(
+    x_bounds: (f32, f32),
+    y_bounds: (f32, f32),
+    rng: &mut impl Rng,
+) -> Vec2 {
     let x = rng.gen_range(x_bounds.0..=x_bounds.1);
     let y = rng.gen_range(y_bounds.0..=y_bounds.1);
     Vec2::new(x, y)
@@ -14,8 +66,7 @@ pub fn f32_to_vec2(f: (f32, f32)) -> Vec2 {
     Vec2::new(f.0, f.1)
 }
 
-pub fn bernoulli_trial(probability: f32) -> bool {
-    let mut rng = rand::thread_rng();
+pub fn bernoulli_trial(probability: f32, rng: &mut impl Rng) -> bool {
     rng.gen_bool(probability as f64)
 }
 
@@ -23,6 +74,7 @@ pub fn vec2_to_i32(vec: Vec2) -> (i32, i32) {
     (vec.x as i32, vec.y as i32)
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Grid2d<T: Default + Clone + PartialEq> {
     pub width: usize,
     pub height: usize,